@@ -0,0 +1,36 @@
+#version 450
+
+layout(location = 0) in vec2 outUV;
+layout(location = 0) out vec4 fragColor;
+
+layout(set = 0, binding = 0) uniform sampler2D inputImage;
+
+const float THRESHOLD = 1.0;
+const float STRENGTH = 0.6;
+// Blur radius in pixels
+const float RADIUS = 9.0;
+
+// Only the part of a pixel brighter than the threshold glows
+vec3 bright(vec2 uv) {
+    vec3 color = texture(inputImage, uv).rgb;
+    float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    return color * max(luma - THRESHOLD, 0.0) / max(luma, 0.0001);
+}
+
+void main() {
+    vec2 spacing = RADIUS / 3.0 / vec2(textureSize(inputImage, 0));
+    vec3 bloom = vec3(0.0);
+    float weights = 0.0;
+
+    for (int x = -3; x <= 3; x++) {
+        for (int y = -3; y <= 3; y++) {
+            vec2 offset = vec2(x, y);
+            float weight = exp(-dot(offset, offset) / 8.0);
+            bloom += bright(outUV + offset * spacing) * weight;
+            weights += weight;
+        }
+    }
+
+    vec4 color = texture(inputImage, outUV);
+    fragColor = vec4(color.rgb + bloom / weights * STRENGTH, color.a);
+}
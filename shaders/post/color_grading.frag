@@ -0,0 +1,30 @@
+#version 450
+
+layout(location = 0) in vec2 outUV;
+layout(location = 0) out vec4 fragColor;
+
+layout(set = 0, binding = 0) uniform sampler2D inputImage;
+// LUT_SIZE slices of LUT_SIZE x LUT_SIZE next to each other, red along x, green along y, blue selects the slice
+layout(set = 0, binding = 1) uniform sampler2D lut;
+
+const float LUT_SIZE = 16.0;
+
+vec2 slice_uv(vec2 rg, float slice) {
+    vec2 texel = rg * (LUT_SIZE - 1.0) + 0.5;
+    return vec2((slice * LUT_SIZE + texel.x) / (LUT_SIZE * LUT_SIZE), texel.y / LUT_SIZE);
+}
+
+vec3 lookup(vec3 color) {
+    float blue = color.b * (LUT_SIZE - 1.0);
+    float slice = floor(blue);
+    vec3 low = texture(lut, slice_uv(color.rg, slice)).rgb;
+    vec3 high = texture(lut, slice_uv(color.rg, min(slice + 1.0, LUT_SIZE - 1.0))).rgb;
+    return mix(low, high, blue - slice);
+}
+
+void main() {
+    vec4 color = texture(inputImage, outUV);
+    // The LUT works on 0..1, so grade in Reinhard space and map back to HDR for the final tonemap
+    vec3 graded = min(lookup(color.rgb / (1.0 + color.rgb)), vec3(0.999));
+    fragColor = vec4(graded / (1.0 - graded), color.a);
+}
@@ -0,0 +1,46 @@
+#version 450
+
+layout(location = 0) in vec2 outUV;
+layout(location = 0) out vec4 fragColor;
+
+layout(set = 0, binding = 0) uniform sampler2D inputImage;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+// The input is still HDR, edges are detected on a roughly tonemapped and gamma corrected luma
+float luma(vec3 color) {
+    vec3 mapped = color / (1.0 + color);
+    return sqrt(dot(mapped, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(inputImage, 0));
+
+    float lumaNW = luma(texture(inputImage, outUV + vec2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = luma(texture(inputImage, outUV + vec2(1.0, -1.0) * texel).rgb);
+    float lumaSW = luma(texture(inputImage, outUV + vec2(-1.0, 1.0) * texel).rgb);
+    float lumaSE = luma(texture(inputImage, outUV + vec2(1.0, 1.0) * texel).rgb);
+    vec4 center = texture(inputImage, outUV);
+    float lumaM = luma(center.rgb);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Blur along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, -SPAN_MAX, SPAN_MAX) * texel;
+
+    vec3 rgbA = 0.5 * (
+        texture(inputImage, outUV + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(inputImage, outUV + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture(inputImage, outUV - dir * 0.5).rgb +
+        texture(inputImage, outUV + dir * 0.5).rgb);
+
+    float lumaB = luma(rgbB);
+    fragColor = vec4((lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB, center.a);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location = 0) in vec2 outUV;
+layout(location = 0) out vec4 fragColor;
+
+layout(set = 0, binding = 0) uniform sampler2D inputImage;
+
+const float INNER = 0.35;
+const float OUTER = 0.85;
+const float STRENGTH = 0.45;
+
+void main() {
+    vec4 color = texture(inputImage, outUV);
+    float radius = length(outUV - 0.5) * 1.414;
+    float falloff = smoothstep(INNER, OUTER, radius);
+    fragColor = vec4(color.rgb * (1.0 - falloff * STRENGTH), color.a);
+}
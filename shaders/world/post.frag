@@ -23,9 +23,16 @@ layout(location = 0) out vec4 fragColor;
 
 layout(set = 0, binding = 0) uniform sampler2D noisyTxt;
 
+// Narkowicz's fit of the ACES filmic curve, maps HDR radiance into 0..1
+vec3 tonemap(vec3 color)
+{
+  return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
 void main()
 {
-  //float gamma = 1. / 2.2;
-  //fragColor   = pow(texture(noisyTxt, outUV).rgba, vec4(gamma));
-  fragColor = texture(noisyTxt, outUV);
+  vec4 hdr = texture(noisyTxt, outUV);
+  // The swapchain is UNORM, so the shader has to apply the gamma
+  float gamma = 1. / 2.2;
+  fragColor = vec4(pow(tonemap(hdr.rgb), vec3(gamma)), hdr.a);
 }
@@ -1,11 +1,120 @@
 #version 450
 
-layout(binding = 1) uniform sampler2D texSampler;
+#define MAX_POINT_LIGHTS 4
+#define SAMPLER_PRESETS 8
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 viewProj;
+    vec4 cameraPosition;
+    vec4 sunDirection;
+    vec4 sunColor;
+    vec4 ambientColor;
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    uvec4 pointLightCount;
+    mat4 lightViewProj;
+    vec4 shadowParams;
+} ubo;
+
+layout(binding = 1) uniform sampler2DArray texSamplers[SAMPLER_PRESETS];
+layout(binding = 2) uniform sampler2DShadow shadowMap;
 
 layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec3 localPos;
+layout(location = 2) flat in uint flags;
+layout(location = 3) flat in vec4 baseColor;
+layout(location = 4) flat in int textureLayer;
+layout(location = 5) flat in float roughness;
+layout(location = 6) in vec3 worldPos;
+layout(location = 7) in vec3 worldNormal;
+layout(location = 8) flat in uint samplerIndex;
 
 layout(location = 0) out vec4 outColor;
 
+const uint SELECTED = 1u;
+const uint HOVERED = 2u;
+
+const vec3 SELECTED_COLOR = vec3(1.0, 0.6, 0.1);
+const vec3 HOVERED_COLOR = vec3(1.0);
+
+// 1 on the edges of the unit cube: at least two coordinates are within ~1.5 pixels of a face
+float outline() {
+    vec3 faceDistance = 0.5 - abs(localPos);
+    vec3 nearFace = step(faceDistance, fwidth(localPos) * 1.5);
+    return step(2.0, nearFace.x + nearFace.y + nearFace.z);
+}
+
+// Blinn-Phong, rough surfaces get a wide and weak highlight
+vec3 shade(vec3 albedo, vec3 normal, vec3 viewDir, vec3 lightDir, vec3 lightColor) {
+    float diffuse = max(dot(normal, lightDir), 0.0);
+    vec3 halfway = normalize(lightDir + viewDir);
+    float shininess = mix(128.0, 4.0, roughness);
+    float specular = pow(max(dot(normal, halfway), 0.0), shininess) * (1.0 - roughness) * diffuse;
+    return (albedo * diffuse + vec3(specular)) * lightColor;
+}
+
+// 3x3 PCF over the sun shadow map, 1 is fully lit
+float sunShadow(vec3 normal) {
+    vec4 lightClip = ubo.lightViewProj * vec4(worldPos + normal * ubo.shadowParams.y, 1.0);
+    vec3 projected = lightClip.xyz / lightClip.w;
+    if (projected.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadowMap, vec3(uv + vec2(x, y) * ubo.shadowParams.x, projected.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// Constant indices only, indexing a sampler array with a variable needs an extra device feature.
+// Instances are batched by sampler, so the branch is the same for the whole draw.
+vec4 sampleTexture(vec3 uvw) {
+    switch (samplerIndex) {
+        case 0u: return texture(texSamplers[0], uvw);
+        case 1u: return texture(texSamplers[1], uvw);
+        case 2u: return texture(texSamplers[2], uvw);
+        case 3u: return texture(texSamplers[3], uvw);
+        case 4u: return texture(texSamplers[4], uvw);
+        case 5u: return texture(texSamplers[5], uvw);
+        case 6u: return texture(texSamplers[6], uvw);
+        default: return texture(texSamplers[7], uvw);
+    }
+}
+
 void main() {
-    outColor = vec4(0.01, fragUv, 1);
-}
\ No newline at end of file
+    vec4 albedo = baseColor;
+    if (textureLayer >= 0) {
+        albedo *= sampleTexture(vec3(fragUv, textureLayer));
+    }
+
+    vec3 normal = normalize(worldNormal);
+    vec3 viewDir = normalize(ubo.cameraPosition.xyz - worldPos);
+
+    vec3 color = albedo.rgb * ubo.ambientColor.rgb;
+    color += shade(albedo.rgb, normal, viewDir, normalize(-ubo.sunDirection.xyz), ubo.sunColor.rgb) * sunShadow(normal);
+
+    for (uint i = 0u; i < min(ubo.pointLightCount.x, uint(MAX_POINT_LIGHTS)); i++) {
+        PointLight light = ubo.pointLights[i];
+        vec3 toLight = light.position.xyz - worldPos;
+        float distance = length(toLight);
+        float attenuation = pow(clamp(1.0 - distance / light.position.w, 0.0, 1.0), 2.0);
+        color += shade(albedo.rgb, normal, viewDir, toLight / distance, light.color.rgb) * attenuation;
+    }
+
+    if ((flags & SELECTED) != 0u) {
+        color = mix(mix(color, SELECTED_COLOR, 0.35), SELECTED_COLOR, outline());
+    } else if ((flags & HOVERED) != 0u) {
+        color = mix(mix(color, HOVERED_COLOR, 0.15), HOVERED_COLOR, outline());
+    }
+
+    outColor = vec4(color, albedo.a);
+}
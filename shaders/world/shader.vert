@@ -1,7 +1,22 @@
 #version 460
 
+#define MAX_POINT_LIGHTS 4
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
 layout(binding = 0) uniform UniformBufferObject {
     mat4 viewProj;
+    vec4 cameraPosition;
+    vec4 sunDirection;
+    vec4 sunColor;
+    vec4 ambientColor;
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    uvec4 pointLightCount;
+    mat4 lightViewProj;
+    vec4 shadowParams;
 } ubo;
 
 layout(location = 0) in vec3 inPosition;
@@ -9,13 +24,36 @@ layout(location = 1) in vec3 nrm;
 layout(location = 2) in vec2 uv;
 layout(location = 3) in float materialIndex;
 layout(location = 4) in mat4 modelMatrix;
+layout(location = 8) in uint instanceFlags;
+layout(location = 9) in vec4 baseColor;
+layout(location = 10) in int textureLayer;
+layout(location = 11) in float roughness;
+layout(location = 12) in uint samplerIndex;
 
 layout(location = 0) out vec2 FragUv;
+layout(location = 1) out vec3 LocalPos;
+layout(location = 2) flat out uint Flags;
+layout(location = 3) flat out vec4 BaseColor;
+layout(location = 4) flat out int TextureLayer;
+layout(location = 5) flat out float Roughness;
+layout(location = 6) out vec3 WorldPos;
+layout(location = 7) out vec3 WorldNormal;
+layout(location = 8) flat out uint SamplerIndex;
 
 
 void main() {
     //vec2 outUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
     //gl_Position = ubo.viewProj * vec4(outUV * inSize + inPosition, 0.0, 1.0);
-    gl_Position = ubo.viewProj * modelMatrix * vec4(inPosition, 1);
+    vec4 worldPos = modelMatrix * vec4(inPosition, 1);
+    gl_Position = ubo.viewProj * worldPos;
     FragUv = uv;
-}
\ No newline at end of file
+    LocalPos = inPosition;
+    Flags = instanceFlags;
+    BaseColor = baseColor;
+    TextureLayer = textureLayer;
+    Roughness = roughness;
+    SamplerIndex = samplerIndex;
+    WorldPos = worldPos.xyz;
+    // Non uniform box scales need the inverse transpose to keep normals perpendicular
+    WorldNormal = mat3(transpose(inverse(modelMatrix))) * nrm;
+}
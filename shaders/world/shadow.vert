@@ -0,0 +1,27 @@
+#version 460
+
+#define MAX_POINT_LIGHTS 4
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 viewProj;
+    vec4 cameraPosition;
+    vec4 sunDirection;
+    vec4 sunColor;
+    vec4 ambientColor;
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    uvec4 pointLightCount;
+    mat4 lightViewProj;
+    vec4 shadowParams;
+} ubo;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 4) in mat4 modelMatrix;
+
+void main() {
+    gl_Position = ubo.lightViewProj * modelMatrix * vec4(inPosition, 1);
+}
@@ -8,10 +8,10 @@ use std::{
 use iron_oxide::{primitives::Vec2, ui::{DirtyFlags, UiEvent, UiState}};
 use log::info;
 use winit::{
-    application::ApplicationHandler, dpi::{PhysicalPosition, PhysicalSize}, event::{ElementState, MouseButton, TouchPhase, WindowEvent}, event_loop::{ActiveEventLoop, ControlFlow}, keyboard::{KeyCode, PhysicalKey}, window::{Theme, Window, WindowId}
+    application::ApplicationHandler, dpi::{PhysicalPosition, PhysicalSize}, event::{ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent}, event_loop::{ActiveEventLoop, ControlFlow}, keyboard::{KeyCode, PhysicalKey}, window::{Theme, Window, WindowId}
 };
-use crate::graphics::VulkanRender;
-use super::{states::build_main, World};
+use crate::graphics::{save_screenshot, PostEffect, ScreenshotMode, VulkanRender, FONT_SHADERS, UI_SHADERS};
+use super::{states::build_main, CameraMode, Projection, World};
 
 const WIDTH: u32 = 1280;
 const HEIGHT: u32 = 720;
@@ -29,6 +29,9 @@ pub struct App {
     pub last_cursor_location: PhysicalPosition<f64>,
     pub touch_id: u64,
     pub mouse_pressed: bool,
+    /// Middle mouse drags pan the orbit camera.
+    pub middle_pressed: bool,
+    pub press_position: PhysicalPosition<f64>,
     pub sim_speed: f32,
     pub target_frame_time: f32,
 }
@@ -38,8 +41,10 @@ impl App {
     pub fn run() -> Self {
         #[allow(invalid_value)]
         let renderer = Rc::new(RefCell::new(unsafe { MaybeUninit::uninit().assume_init() }));
-        let ui: Rc<RefCell<UiState>> = Rc::new(RefCell::new(build_main()));
-        let world = World::create(renderer.clone(), ui.clone());
+        // Filled in once the world is loaded, the sidebar shows its viewpoints
+        let ui: Rc<RefCell<UiState>> = Rc::new(RefCell::new(UiState::create(true)));
+        let world = World::create(ui.clone());
+        ui.replace(build_main(&world.sidebar()));
 
         Self {
             window: MaybeUninit::uninit(),
@@ -52,6 +57,8 @@ impl App {
             last_cursor_location: PhysicalPosition { x: 0.0, y: 0.0 },
             touch_id: 0,
             mouse_pressed: false,
+            middle_pressed: false,
+            press_position: PhysicalPosition { x: 0.0, y: 0.0 },
             sim_speed: 1.0,
             target_frame_time: 1.0 / 144.0,
         }
@@ -60,6 +67,12 @@ impl App {
     pub fn window(&self) -> &Window {
         unsafe { self.window.assume_init_ref() }
     }
+
+    fn autosave(&self) {
+        if let Err(err) = self.world.save() {
+            log::error!("autosave failed: {err}");
+        }
+    }
 }
 
 impl ApplicationHandler for App {
@@ -78,27 +91,56 @@ impl ApplicationHandler for App {
                     in_ui = ui.update_cursor(position.into(), UiEvent::Move);
                 }
 
+                let delta = Vec2::new(self.cursor_pos.x as f32 - position.x as f32, self.cursor_pos.y as f32 - position.y as f32);
                 if in_ui.is_none() && self.mouse_pressed {
-                    let delta = Vec2::new(self.cursor_pos.x as f32 - position.x as f32, self.cursor_pos.y as f32 - position.y as f32);
                     self.world.camera.process_mouse_movement(delta, 0.25);
+                } else if in_ui.is_none() && self.middle_pressed {
+                    self.world.camera.process_pan(delta);
                 }
 
+                self.world.hovered = if in_ui.is_none() && !self.mouse_pressed {
+                    self.world.pick(position.into(), window_size_vec(renderer.window_size))
+                } else {
+                    None
+                };
+
                 self.cursor_pos = position;
             },
             WindowEvent::MouseInput { device_id: _, state, button } => {
                 match button {
                     MouseButton::Left => {
                         self.mouse_pressed = state == ElementState::Pressed;
-                        renderer.ui_state.borrow_mut().update_cursor(self.cursor_pos.into(), 
+                        let in_ui = renderer.ui_state.borrow_mut().update_cursor(self.cursor_pos.into(), 
                             match state {
                                 ElementState::Pressed => UiEvent::Press,
                                 ElementState::Released => UiEvent::Release,
                             }
                         );
+
+                        match state {
+                            ElementState::Pressed => self.press_position = self.cursor_pos,
+                            // Only a click selects, dragging rotates the camera
+                            ElementState::Released => {
+                                let dragged = (self.cursor_pos.x - self.press_position.x).abs() + (self.cursor_pos.y - self.press_position.y).abs();
+                                if in_ui.is_none() && dragged < 4.0 {
+                                    let picked = self.world.pick(self.cursor_pos.into(), window_size_vec(renderer.window_size));
+                                    self.world.select(picked);
+                                }
+                            }
+                        }
                     }
+                    MouseButton::Middle => self.middle_pressed = state == ElementState::Pressed,
                     _ => ()
                 }
             },
+            WindowEvent::MouseWheel { device_id: _, delta, phase: _ } => {
+                // Touchpads report pixels, roughly 40 of them per wheel notch
+                let amount = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                self.world.camera.process_zoom(amount);
+            },
             WindowEvent::Touch(touch) => {
                 let cursor_pos = touch.location.into();
                 match touch.phase {
@@ -122,8 +164,18 @@ impl ApplicationHandler for App {
                 let time_stamp = self.time.elapsed().as_secs_f32();
                 if !FPS_LIMIT || time_stamp > self.target_frame_time * 0.93 {
                     self.time = Instant::now();
-                    self.world.update(self.sim_speed * time_stamp, &mut renderer);
+                    self.world.update(time_stamp, self.sim_speed);
+                    if let Some(sidebar) = self.world.take_sidebar() {
+                        renderer.replace_ui(build_main(&sidebar));
+                    }
                     renderer.draw_frame();
+
+                    if let Some((size, pixels)) = renderer.take_screenshot() {
+                        match save_screenshot(size, &pixels) {
+                            Ok(path) => info!("saved screenshot to {}", path.display()),
+                            Err(err) => log::error!("failed to save screenshot: {err}"),
+                        }
+                    }
                 } else {
                     sleep(Duration::from_nanos(800_000));
                 };
@@ -131,6 +183,19 @@ impl ApplicationHandler for App {
             WindowEvent::KeyboardInput { device_id: _, event, is_synthetic: _ } => {
                 if let PhysicalKey::Code(key_code) = event.physical_key {
 
+                    // While a viewpoint is named the keyboard types, released keys still stop the movement below
+                    if self.world.viewpoint_draft.is_some() && event.state.is_pressed() {
+                        match key_code {
+                            KeyCode::Enter | KeyCode::NumpadEnter => self.world.finish_viewpoint_name(),
+                            KeyCode::Escape => self.world.cancel_viewpoint_name(),
+                            KeyCode::Backspace => self.world.erase_viewpoint_name(),
+                            _ => if let Some(text) = &event.text {
+                                self.world.type_viewpoint_name(text);
+                            },
+                        }
+                        return;
+                    }
+
                     match key_code {
                         KeyCode::F1 => {
                             if event.state.is_pressed() {
@@ -141,6 +206,76 @@ impl ApplicationHandler for App {
                                 }
                             }
                         },
+                        KeyCode::F2 => {
+                            if event.state.is_pressed() && !event.repeat {
+                                renderer.screenshot_request = Some(ScreenshotMode::WithUi);
+                            }
+                        },
+                        KeyCode::F3 => {
+                            if event.state.is_pressed() && !event.repeat {
+                                renderer.screenshot_request = Some(ScreenshotMode::WorldOnly);
+                            }
+                        },
+                        KeyCode::F5 | KeyCode::F6 | KeyCode::F7 | KeyCode::F8 => {
+                            if event.state.is_pressed() && !event.repeat {
+                                let effect = match key_code {
+                                    KeyCode::F5 => PostEffect::Bloom,
+                                    KeyCode::F6 => PostEffect::Fxaa,
+                                    KeyCode::F7 => PostEffect::Vignette,
+                                    _ => PostEffect::ColorGrading,
+                                };
+                                let enabled = renderer.post_process.toggle(effect);
+                                info!("{effect:?} {}", if enabled { "enabled" } else { "disabled" });
+                            }
+                        },
+                        KeyCode::F9 => {
+                            if event.state.is_pressed() && !event.repeat {
+                                // Cycles 1, 2, 4, 8, values the device does not support are clamped
+                                let current = renderer.msaa_samples.as_raw();
+                                let samples = renderer.set_msaa(if current >= 8 { 1 } else { current * 2 });
+                                if samples.as_raw() <= current && current != 8 {
+                                    renderer.set_msaa(1);
+                                }
+                                info!("MSAA {}x", renderer.msaa_samples.as_raw());
+                            }
+                        },
+                        KeyCode::KeyO => {
+                            if event.state.is_pressed() && !event.repeat {
+                                let camera = &mut self.world.camera;
+                                camera.set_mode(if camera.mode == CameraMode::Orbit { CameraMode::FreeFly } else { CameraMode::Orbit });
+                                info!("camera {:?}", camera.mode);
+                            }
+                        },
+                        KeyCode::KeyP => {
+                            if event.state.is_pressed() && !event.repeat {
+                                let camera = &mut self.world.camera;
+                                camera.set_projection(if camera.projection_mode == Projection::Orthographic { Projection::Perspective } else { Projection::Orthographic });
+                                info!("projection {:?}", camera.projection_mode);
+                            }
+                        },
+                        KeyCode::KeyT => {
+                            if event.state.is_pressed() && !event.repeat {
+                                self.world.camera.toggle_floor_plan();
+                                info!("camera {:?}", self.world.camera.mode);
+                            }
+                        },
+                        KeyCode::KeyB => {
+                            if event.state.is_pressed() && !event.repeat {
+                                self.world.start_viewpoint_name();
+                            }
+                        },
+                        KeyCode::Digit1 | KeyCode::Digit2 | KeyCode::Digit3 | KeyCode::Digit4 | KeyCode::Digit5
+                        | KeyCode::Digit6 | KeyCode::Digit7 | KeyCode::Digit8 | KeyCode::Digit9 => {
+                            if event.state.is_pressed() && !event.repeat {
+                                const DIGITS: [KeyCode; 9] = [
+                                    KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5,
+                                    KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+                                ];
+                                if let Some(index) = DIGITS.iter().position(|digit| *digit == key_code) {
+                                    self.world.fly_to(index);
+                                }
+                            }
+                        },
                         KeyCode::KeyX => {
                             if event.state.is_pressed() {
                                 if self.sim_speed == 0.0 {
@@ -207,6 +342,19 @@ impl ApplicationHandler for App {
                 renderer.recreate_swapchain(size);
                 self.world.camera.moved = true;
             },
+            WindowEvent::DroppedFile(path) => {
+                // Dropped models appear a few units in front of the camera, in the floor plan on the grid under the cursor
+                let floor_point = (self.world.camera.mode == CameraMode::FloorPlan)
+                    .then(|| self.world.floor_point(self.cursor_pos.into(), window_size_vec(renderer.window_size)))
+                    .flatten();
+                let (position, on_floor) = match floor_point {
+                    Some(point) => (point, true),
+                    None => (self.world.camera.position + self.world.camera.front() * 4.0, false),
+                };
+                if let Err(err) = self.world.import_model(&path, position, on_floor) {
+                    log::error!("failed to import {}: {err}", path.display());
+                }
+            },
             WindowEvent::CloseRequested => {
                 event_loop.exit();
                 unsafe { renderer.base.device.device_wait_idle().unwrap_unchecked() };
@@ -226,6 +374,7 @@ impl ApplicationHandler for App {
         if !self.init {
             return;
         }
+        self.autosave();
         self.init = false;
         let mut renderer = self.renderer.borrow_mut();
         unsafe { renderer.base.device.device_wait_idle().unwrap_unchecked(); };
@@ -260,12 +409,9 @@ impl ApplicationHandler for App {
 
         let mut renderer = self.renderer.borrow_mut();
         
-        let shaders = (include_bytes!("../../spv/basic.vert.spv").as_ref(), include_bytes!("../../spv/basic.frag.spv").as_ref());
-        let font_shaders = (include_bytes!("../../spv/bitmap.vert.spv").as_ref(), include_bytes!("../../spv/bitmap.frag.spv").as_ref());
-        
         {
             let mut ui = self.ui.borrow_mut();
-            ui.init_graphics(&renderer.base, renderer.window_size, renderer.render_pass, renderer.ui_descriptor_set_layout, shaders, font_shaders);
+            ui.init_graphics(&renderer.base, renderer.window_size, renderer.render_pass, renderer.ui_descriptor_set_layout, UI_SHADERS, FONT_SHADERS);
         }
         
         renderer.draw_frame();
@@ -282,6 +428,11 @@ impl ApplicationHandler for App {
         if !self.init {
             return;
         }
+        self.autosave();
         self.init = false;
     }
-}
\ No newline at end of file
+}
+
+fn window_size_vec(size: PhysicalSize<u32>) -> Vec2 {
+    Vec2::new(size.width as f32, size.height as f32)
+}
@@ -1,5 +1,36 @@
 use iron_oxide::primitives::{Vec2, Vec3};
-use cgmath::{Matrix4, Point3, Vector3};
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use crate::graphics::DEPTH_CORRECTION;
+
+/// Closest and furthest the orbit camera zooms towards its focus.
+const MIN_ORBIT_DISTANCE: f32 = 0.5;
+const MAX_ORBIT_DISTANCE: f32 = 100.0;
+/// How fast a re-center closes the gap to its target, per second.
+const RECENTER_RATE: f32 = 8.0;
+/// Smallest and largest world height an orthographic view shows.
+const MIN_ORTHO_HEIGHT: f32 = 1.0;
+const MAX_ORTHO_HEIGHT: f32 = 200.0;
+/// The floor plan looks down from above anything that gets stored.
+const FLOOR_PLAN_HEIGHT: f32 = 50.0;
+/// Spacing of the floor plan grid that placed objects snap to, in world units.
+pub const GRID_SIZE: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Yaw and pitch around the camera's own position, moved with WASD.
+    FreeFly,
+    /// Yaw and pitch around `Camera::focus`, at `Camera::distance` from it.
+    Orbit,
+    /// Locked top-down orthographic view, dragging and WASD slide the plan.
+    FloorPlan,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel projection showing `Camera::ortho_height` world units from top to bottom.
+    Orthographic,
+}
 
 pub struct Camera {
     pub position: Vec3,
@@ -9,43 +40,226 @@ pub struct Camera {
     pub near: f32,
     pub far: f32,
     pub moved: bool,
+    pub mode: CameraMode,
+    /// Point the orbit mode rotates around.
+    pub focus: Vec3,
+    pub distance: f32,
+    /// Focus the orbit is easing towards after `recenter`.
+    pub focus_target: Option<Vec3>,
+    pub projection_mode: Projection,
+    pub ortho_height: f32,
+    /// Mode, position, yaw, pitch and projection to go back to when leaving the floor plan.
+    pub previous_view: Option<(CameraMode, Vec3, f32, f32, Projection)>,
 }
 
 impl Camera {
 
+    /// The floor plan can not be rotated, dragging slides it instead.
     pub fn process_mouse_movement(&mut self, delta: Vec2, sensitivity: f32) {
+        if self.mode == CameraMode::FloorPlan {
+            self.process_pan(delta);
+            return;
+        }
         self.yaw += delta.x * sensitivity;
         self.pitch += delta.y * sensitivity;
         self.pitch = self.pitch.clamp(-89.0, 89.0); // Begrenze Pitch
+        if self.mode == CameraMode::Orbit {
+            self.update_orbit_position();
+        }
         self.moved = true;
     }
 
+    /// In orbit mode the focus moves along, so WASD slides the whole orbit. The floor plan keeps its height.
     pub fn process_movement(&mut self, delta: Vec3, speed: f32) {
-        let front = Vec3::new(self.yaw.to_radians().cos(), 0.0, self.yaw.to_radians().sin()).normalize();
+        let front = self.horizontal_front();
         let right = front.cross(Vec3::new(0.0, -1.0, 0.0)).normalize();
 
-        self.position += front * delta.z * speed;
-        self.position += right * delta.x * speed;
-        self.position.y += delta.y * speed;
+        let mut offset = front * delta.z * speed + right * delta.x * speed;
+        if self.mode != CameraMode::FloorPlan {
+            offset.y += delta.y * speed;
+        }
+
+        self.position += offset;
+        if self.mode == CameraMode::Orbit {
+            self.focus += offset;
+            self.focus_target = None;
+        }
         self.moved = true;
     }
 
+    /// Positive `amount` zooms in. Orthographic views shrink the visible area,
+    /// orbit mode scales the distance to the focus and free fly moves forward.
+    pub fn process_zoom(&mut self, amount: f32) {
+        let factor = 0.9f32.powf(amount);
+        match (self.projection_mode, self.mode) {
+            (Projection::Orthographic, _) | (_, CameraMode::FloorPlan) => {
+                self.ortho_height = (self.ortho_height * factor).clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT);
+            },
+            (Projection::Perspective, CameraMode::Orbit) => {
+                self.distance = (self.distance * factor).clamp(MIN_ORBIT_DISTANCE, MAX_ORBIT_DISTANCE);
+                self.update_orbit_position();
+            },
+            (Projection::Perspective, CameraMode::FreeFly) => self.position += self.front() * amount * 0.5,
+        }
+        self.moved = true;
+    }
+
+    /// Drags the view with the cursor, `delta` in pixels. Pans further the further away the focus is
+    /// or the more the orthographic view shows.
+    pub fn process_pan(&mut self, delta: Vec2) {
+        let (right, down, scale) = match self.mode {
+            CameraMode::FreeFly => return,
+            CameraMode::Orbit => {
+                let front = self.front();
+                let right = front.cross(Vec3::new(0.0, -1.0, 0.0)).normalize();
+                // Window y grows downwards, like this axis in world space
+                (right, right.cross(front).normalize(), self.distance * 0.0015)
+            },
+            CameraMode::FloorPlan => {
+                let front = self.horizontal_front();
+                (front.cross(Vec3::new(0.0, -1.0, 0.0)).normalize(), front * -1.0, self.ortho_height * 0.0015)
+            },
+        };
+
+        let offset = right * delta.x * scale + down * delta.y * scale;
+        if self.mode == CameraMode::Orbit {
+            self.focus += offset;
+            self.focus_target = None;
+            self.update_orbit_position();
+        } else {
+            self.position += offset;
+        }
+        self.moved = true;
+    }
+
+    /// Switches between the modes without the view jumping. The orbit starts around the point the camera looks at,
+    /// leaving the floor plan returns to where the camera was before it.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == self.mode {
+            return;
+        }
+
+        if let Some((_, position, yaw, pitch, projection_mode)) = self.previous_view.take() {
+            self.position = position;
+            self.yaw = yaw;
+            self.pitch = pitch;
+            self.projection_mode = projection_mode;
+        }
+
+        match mode {
+            CameraMode::Orbit => self.focus = self.position + self.front() * self.distance,
+            CameraMode::FloorPlan => {
+                self.previous_view = Some((self.mode, self.position, self.yaw, self.pitch, self.projection_mode));
+                self.set_projection(Projection::Orthographic);
+                self.position.y = FLOOR_PLAN_HEIGHT;
+                self.pitch = -90.0;
+            },
+            CameraMode::FreeFly => (),
+        }
+        self.focus_target = None;
+        self.mode = mode;
+        self.moved = true;
+    }
+
+    /// Leaving the floor plan goes back to the mode it was entered from.
+    pub fn toggle_floor_plan(&mut self) {
+        match self.previous_view {
+            Some((previous_mode, ..)) if self.mode == CameraMode::FloorPlan => self.set_mode(previous_mode),
+            _ => self.set_mode(CameraMode::FloorPlan),
+        }
+    }
+
+    /// Switching to orthographic keeps things at the focus distance the same size on screen. The floor plan stays orthographic.
+    pub fn set_projection(&mut self, projection_mode: Projection) {
+        if self.mode == CameraMode::FloorPlan || projection_mode == self.projection_mode {
+            return;
+        }
+        if projection_mode == Projection::Orthographic {
+            self.ortho_height = (2.0 * self.distance * (self.fov.to_radians() / 2.0).tan()).clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT);
+        }
+        self.projection_mode = projection_mode;
+        self.moved = true;
+    }
+
+    /// Places the camera directly. In orbit mode the focus moves along and stays in front of the camera.
+    pub fn set_view(&mut self, position: Vec3, yaw: f32, pitch: f32, fov: f32) {
+        self.position = position;
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89.0, 89.0);
+        self.fov = fov;
+        if self.mode == CameraMode::Orbit {
+            self.focus = self.position + self.front() * self.distance;
+            self.focus_target = None;
+        }
+        self.moved = true;
+    }
+
+    /// Eases the orbit focus towards `target` over the next frames. Ignored in free fly.
+    pub fn recenter(&mut self, target: Vec3) {
+        if self.mode == CameraMode::Orbit {
+            self.focus_target = Some(target);
+        }
+    }
+
+    /// Advances a running re-center.
+    pub fn update(&mut self, delta_time: f32) {
+        let Some(target) = self.focus_target else { return };
+
+        let remaining: Vector3<f32> = (target - self.focus).into();
+        if remaining.magnitude() < 0.001 {
+            self.focus = target;
+            self.focus_target = None;
+        } else {
+            // Frame rate independent exponential ease out
+            self.focus += (target - self.focus) * (1.0 - (-RECENTER_RATE * delta_time).exp());
+        }
+        self.update_orbit_position();
+        self.moved = true;
+    }
+
+    fn horizontal_front(&self) -> Vec3 {
+        Vec3::new(self.yaw.to_radians().cos(), 0.0, self.yaw.to_radians().sin()).normalize()
+    }
+
+    fn update_orbit_position(&mut self) {
+        self.position = self.focus - self.front() * self.distance;
+    }
+
     pub fn view(&mut self) -> Matrix4<f32> {
         self.moved = false;
+        self.view_matrix()
+    }
+
+    pub fn front(&self) -> Vec3 {
         let yaw_radians = self.yaw.to_radians();
         let pitch_radians = self.pitch.to_radians();
 
-        let front = Vec3::new(
+        Vec3::new(
             yaw_radians.cos() * pitch_radians.cos(),
             pitch_radians.sin(),
             yaw_radians.sin() * pitch_radians.cos(),
-        ).normalize();
+        ).normalize()
+    }
 
-        Matrix4::look_to_rh(Point3::new(self.position.x, self.position.y, self.position.z), front.into(), Vector3::new(0.0, -1.0, 0.0))
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        // Looking straight down, -y would be parallel to the view direction, the yaw decides what is up on screen instead
+        let up = match self.mode {
+            CameraMode::FloorPlan => (self.horizontal_front() * -1.0).into(),
+            _ => Vector3::new(0.0, -1.0, 0.0),
+        };
+        Matrix4::look_to_rh(Point3::new(self.position.x, self.position.y, self.position.z), self.front().into(), up)
     }
 
+    /// Maps the view volume to Vulkan's depth range of 0..1, near lands on 0.
     pub fn projection(&self, aspect_ratio: f32) -> Matrix4<f32> {
-        cgmath::perspective(cgmath::Deg(self.fov), aspect_ratio, self.near, self.far)
+        let projection = match self.projection_mode {
+            Projection::Perspective => cgmath::perspective(cgmath::Deg(self.fov), aspect_ratio, self.near, self.far),
+            Projection::Orthographic => {
+                let (half_width, half_height) = (self.ortho_height * aspect_ratio / 2.0, self.ortho_height / 2.0);
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            },
+        };
+        DEPTH_CORRECTION * projection
     }
 }
 
@@ -59,6 +273,18 @@ impl Default for Camera {
             near: 0.1,
             far: 1000.0,
             moved: true,
+            mode: CameraMode::FreeFly,
+            focus: Vec3::new(0.0, 0.0, -5.0),
+            distance: 5.0,
+            focus_target: None,
+            projection_mode: Projection::Perspective,
+            ortho_height: 10.0,
+            previous_view: None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Rounds x and z to the floor plan grid, y is left alone.
+pub fn snap_to_grid(position: Vec3) -> Vec3 {
+    Vec3::new((position.x / GRID_SIZE).round() * GRID_SIZE, position.y, (position.z / GRID_SIZE).round() * GRID_SIZE)
+}
@@ -0,0 +1,107 @@
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+/// Six planes facing into the visible volume, a point `p` is inside a plane if `dot(plane.xyz, p) + plane.w >= 0`.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view projection matrix (Gribb/Hartmann).
+    /// Expects Vulkan's depth range of 0..1 like `Camera::projection` and the sun's matrix produce.
+    pub fn from_view_proj(view_proj: Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(view_proj.x[i], view_proj.y[i], view_proj.z[i], view_proj.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().magnitude());
+
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> [Vector4<f32>; 6] {
+        self.planes
+    }
+
+    /// Conservative test, boxes close to an edge of the frustum may pass while being just outside.
+    pub fn intersects_aabb(&self, center: Vector3<f32>, size: Vector3<f32>) -> bool {
+        let half = size * 0.5;
+
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // Distance from the center to the box corner furthest along the plane normal
+            let radius = half.x * normal.x.abs() + half.y * normal.y.abs() + half.z * normal.z.abs();
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Matrix4, Point3, Vector3};
+    use crate::graphics::DEPTH_CORRECTION;
+    use super::Frustum;
+
+    // Camera at the origin looking down -z, near 1 and far 100
+    fn view() -> Matrix4<f32> {
+        Matrix4::look_to_rh(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0))
+    }
+
+    // 90° and a square aspect ratio, at distance d the frustum spans -d..d on x and y
+    fn perspective() -> Frustum {
+        Frustum::from_view_proj(DEPTH_CORRECTION * cgmath::perspective(Deg(90.0), 1.0, 1.0, 100.0) * view())
+    }
+
+    fn visible(frustum: &Frustum, center: [f32; 3], size: f32) -> bool {
+        frustum.intersects_aabb(Vector3::from(center), Vector3::new(size, size, size))
+    }
+
+    #[test]
+    fn box_inside_is_visible() {
+        assert!(visible(&perspective(), [0.0, 0.0, -10.0], 1.0));
+        assert!(visible(&perspective(), [8.0, -8.0, -50.0], 1.0));
+    }
+
+    #[test]
+    fn box_outside_each_plane_is_culled() {
+        let frustum = perspective();
+
+        for (center, plane) in [
+            ([-30.0, 0.0, -10.0], "left"),
+            ([30.0, 0.0, -10.0], "right"),
+            ([0.0, -30.0, -10.0], "bottom"),
+            ([0.0, 30.0, -10.0], "top"),
+            ([0.0, 0.0, 2.0], "near"),
+            ([0.0, 0.0, -200.0], "far"),
+        ] {
+            assert!(!visible(&frustum, center, 1.0), "box beyond the {plane} plane is visible");
+        }
+        // Between the camera and the near plane
+        assert!(!visible(&frustum, [0.0, 0.0, -0.5], 0.5));
+    }
+
+    #[test]
+    fn box_straddling_an_edge_is_visible() {
+        let frustum = perspective();
+
+        // Centers just outside the right plane and the far plane, the boxes reach back in
+        assert!(visible(&frustum, [10.5, 0.0, -10.0], 2.0));
+        assert!(visible(&frustum, [0.0, 0.0, -100.5], 2.0));
+        // Around the camera the box crosses the near plane
+        assert!(visible(&frustum, [0.0, 0.0, 0.0], 4.0));
+    }
+
+    #[test]
+    fn orthographic_frustum_is_a_box() {
+        let frustum = Frustum::from_view_proj(DEPTH_CORRECTION * cgmath::ortho(-5.0, 5.0, -5.0, 5.0, 1.0, 100.0) * view());
+
+        // The sides do not widen with the distance
+        assert!(visible(&frustum, [4.0, 0.0, -2.0], 1.0));
+        assert!(visible(&frustum, [4.0, -4.0, -90.0], 1.0));
+        assert!(!visible(&frustum, [7.0, 0.0, -90.0], 1.0));
+        assert!(!visible(&frustum, [0.0, -7.0, -2.0], 1.0));
+        assert!(visible(&frustum, [5.5, 0.0, -50.0], 2.0));
+        assert!(!visible(&frustum, [0.0, 0.0, -150.0], 1.0));
+        assert!(!visible(&frustum, [0.0, 0.0, 1.0], 1.0));
+        assert!(!visible(&frustum, [0.0, 0.0, -0.5], 0.5));
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocationId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ItemId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocationKind {
+    Room,
+    Shelf,
+    Box,
+}
+
+impl LocationKind {
+    // Rooms may be nested (house -> floor -> room), shelves stand in rooms and boxes go anywhere
+    fn can_be_child_of(self, parent: Option<LocationKind>) -> bool {
+        matches!(
+            (self, parent),
+            (Self::Room, None | Some(Self::Room)) | (Self::Shelf, Some(Self::Room)) | (Self::Box, Some(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageLocation {
+    pub id: LocationId,
+    pub name: String,
+    pub kind: LocationKind,
+    pub parent: Option<LocationId>,
+    /// Index into `World::cubes`. Every box has one, rooms and shelves only if they are drawn.
+    pub cube: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub id: ItemId,
+    pub name: String,
+    pub quantity: u32,
+    pub tags: Vec<String>,
+    pub notes: String,
+    pub location: LocationId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InventoryError {
+    UnknownLocation(LocationId),
+    UnknownItem(ItemId),
+    InvalidParent { kind: LocationKind, parent: Option<LocationKind> },
+    MissingCube(LocationId),
+    NotEmpty(LocationId),
+    CubeInUse(LocationId),
+    UnknownCube(usize),
+    DuplicateLocation(LocationId),
+    DuplicateItem(ItemId),
+    ParentLoop(LocationId),
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownLocation(id) => write!(f, "unknown storage location {}", id.0),
+            Self::UnknownItem(id) => write!(f, "unknown item {}", id.0),
+            Self::InvalidParent { kind, parent } => write!(f, "a {kind:?} cannot be placed in {parent:?}"),
+            Self::MissingCube(id) => write!(f, "box {} needs a cube", id.0),
+            Self::NotEmpty(id) => write!(f, "storage location {} still contains locations or items", id.0),
+            Self::CubeInUse(id) => write!(f, "the cube belongs to storage location {}", id.0),
+            Self::UnknownCube(index) => write!(f, "there is no cube {index}"),
+            Self::DuplicateLocation(id) => write!(f, "storage location {} exists twice", id.0),
+            Self::DuplicateItem(id) => write!(f, "item {} exists twice", id.0),
+            Self::ParentLoop(id) => write!(f, "storage location {} is inside itself", id.0),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Inventory {
+    pub locations: Vec<StorageLocation>,
+    pub items: Vec<Item>,
+    next_location_id: u32,
+    next_item_id: u32,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_parts(locations: Vec<StorageLocation>, items: Vec<Item>) -> Self {
+        let next_location_id = locations.iter().map(|location| location.id.0 + 1).max().unwrap_or(0);
+        let next_item_id = items.iter().map(|item| item.id.0 + 1).max().unwrap_or(0);
+        Self { locations, items, next_location_id, next_item_id }
+    }
+
+    /// Checks the rules `add_location` and `add_item` enforce on an inventory built with `from_parts`.
+    pub fn validate(&self, cube_count: usize) -> Result<(), InventoryError> {
+        for (index, location) in self.locations.iter().enumerate() {
+            if self.locations[..index].iter().any(|other| other.id == location.id) {
+                return Err(InventoryError::DuplicateLocation(location.id));
+            }
+
+            let parent_kind = match location.parent {
+                Some(parent) => Some(self.location(parent).ok_or(InventoryError::UnknownLocation(parent))?.kind),
+                None => None,
+            };
+            if !location.kind.can_be_child_of(parent_kind) {
+                return Err(InventoryError::InvalidParent { kind: location.kind, parent: parent_kind });
+            }
+
+            match location.cube {
+                None if location.kind == LocationKind::Box => return Err(InventoryError::MissingCube(location.id)),
+                Some(cube) if cube >= cube_count => return Err(InventoryError::UnknownCube(cube)),
+                Some(cube) if self.locations[..index].iter().any(|other| other.cube == Some(cube)) => return Err(InventoryError::CubeInUse(location.id)),
+                _ => (),
+            }
+
+            // Rooms may nest, a loop of them would never reach a root
+            let mut ancestor = location.parent;
+            for _ in 0..self.locations.len() {
+                ancestor = ancestor.and_then(|id| self.location(id)).and_then(|location| location.parent);
+            }
+            if ancestor.is_some() {
+                return Err(InventoryError::ParentLoop(location.id));
+            }
+        }
+
+        for (index, item) in self.items.iter().enumerate() {
+            if self.items[..index].iter().any(|other| other.id == item.id) {
+                return Err(InventoryError::DuplicateItem(item.id));
+            }
+            if self.location(item.location).is_none() {
+                return Err(InventoryError::UnknownLocation(item.location));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `cube` indexes `World::cubes`, which holds `cube_count` cubes. A cube represents at most one location.
+    pub fn add_location(&mut self, name: impl Into<String>, kind: LocationKind, parent: Option<LocationId>, cube: Option<usize>, cube_count: usize) -> Result<LocationId, InventoryError> {
+        let parent_kind = match parent {
+            Some(parent) => Some(self.location(parent).ok_or(InventoryError::UnknownLocation(parent))?.kind),
+            None => None,
+        };
+
+        if !kind.can_be_child_of(parent_kind) {
+            return Err(InventoryError::InvalidParent { kind, parent: parent_kind });
+        }
+
+        let id = LocationId(self.next_location_id);
+        if kind == LocationKind::Box && cube.is_none() {
+            return Err(InventoryError::MissingCube(id));
+        }
+        if let Some(cube) = cube {
+            if cube >= cube_count {
+                return Err(InventoryError::UnknownCube(cube));
+            }
+            if let Some(owner) = self.location_for_cube(cube) {
+                return Err(InventoryError::CubeInUse(owner.id));
+            }
+        }
+
+        self.next_location_id += 1;
+        self.locations.push(StorageLocation { id, name: name.into(), kind, parent, cube });
+        Ok(id)
+    }
+
+    pub fn location(&self, id: LocationId) -> Option<&StorageLocation> {
+        self.locations.iter().find(|location| location.id == id)
+    }
+
+    pub fn location_mut(&mut self, id: LocationId) -> Option<&mut StorageLocation> {
+        self.locations.iter_mut().find(|location| location.id == id)
+    }
+
+    pub fn location_for_cube(&self, cube: usize) -> Option<&StorageLocation> {
+        self.locations.iter().find(|location| location.cube == Some(cube))
+    }
+
+    pub fn children(&self, id: LocationId) -> impl Iterator<Item = &StorageLocation> {
+        self.locations.iter().filter(move |location| location.parent == Some(id))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &StorageLocation> {
+        self.locations.iter().filter(|location| location.parent.is_none())
+    }
+
+    /// Human readable path like `Garage / Shelf 3 / Box A`.
+    pub fn path(&self, id: LocationId) -> String {
+        let mut names = Vec::new();
+        let mut current = self.location(id);
+
+        while let Some(location) = current {
+            names.push(location.name.as_str());
+            current = location.parent.and_then(|parent| self.location(parent));
+        }
+
+        names.reverse();
+        names.join(" / ")
+    }
+
+    /// Removes an empty location. If it had a cube the other cube indices already account for
+    /// `World::cubes.remove(cube)`, which the caller does next.
+    pub fn remove_location(&mut self, id: LocationId) -> Result<StorageLocation, InventoryError> {
+        let index = self.locations.iter().position(|location| location.id == id).ok_or(InventoryError::UnknownLocation(id))?;
+
+        if self.children(id).next().is_some() || self.items_in(id).next().is_some() {
+            return Err(InventoryError::NotEmpty(id));
+        }
+
+        let location = self.locations.remove(index);
+        if let Some(cube) = location.cube {
+            self.shift_cubes_after(cube);
+        }
+        Ok(location)
+    }
+
+    /// Keeps the cube indices valid for `World::cubes.remove(index)`, call it before removing the cube.
+    /// Fails without changing anything while a box still uses the cube, remove the box first.
+    pub fn cube_removed(&mut self, index: usize) -> Result<(), InventoryError> {
+        if let Some(location) = self.locations.iter().find(|location| location.kind == LocationKind::Box && location.cube == Some(index)) {
+            return Err(InventoryError::CubeInUse(location.id));
+        }
+
+        self.shift_cubes_after(index);
+        Ok(())
+    }
+
+    fn shift_cubes_after(&mut self, index: usize) {
+        for location in &mut self.locations {
+            match location.cube {
+                Some(cube) if cube == index => location.cube = None,
+                Some(cube) if cube > index => location.cube = Some(cube - 1),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn add_item(&mut self, location: LocationId, name: impl Into<String>, quantity: u32) -> Result<ItemId, InventoryError> {
+        if self.location(location).is_none() {
+            return Err(InventoryError::UnknownLocation(location));
+        }
+
+        let id = ItemId(self.next_item_id);
+        self.next_item_id += 1;
+        self.items.push(Item { id, name: name.into(), quantity, tags: Vec::new(), notes: String::new(), location });
+        Ok(id)
+    }
+
+    pub fn item(&self, id: ItemId) -> Option<&Item> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    pub fn item_mut(&mut self, id: ItemId) -> Option<&mut Item> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    pub fn items_in(&self, location: LocationId) -> impl Iterator<Item = &Item> {
+        self.items.iter().filter(move |item| item.location == location)
+    }
+
+    /// Items stored in `location` or anywhere below it.
+    pub fn items_below(&self, location: LocationId) -> Vec<&Item> {
+        self.items.iter().filter(|item| self.is_inside(item.location, location)).collect()
+    }
+
+    pub fn move_item(&mut self, id: ItemId, location: LocationId) -> Result<(), InventoryError> {
+        if self.location(location).is_none() {
+            return Err(InventoryError::UnknownLocation(location));
+        }
+
+        let item = self.item_mut(id).ok_or(InventoryError::UnknownItem(id))?;
+        item.location = location;
+        Ok(())
+    }
+
+    pub fn remove_item(&mut self, id: ItemId) -> Result<Item, InventoryError> {
+        let index = self.items.iter().position(|item| item.id == id).ok_or(InventoryError::UnknownItem(id))?;
+        Ok(self.items.remove(index))
+    }
+
+    /// Case insensitive search over item names and tags.
+    pub fn search(&self, query: &str) -> Vec<&Item> {
+        let query = query.to_lowercase();
+        self.items.iter().filter(|item| {
+            item.name.to_lowercase().contains(&query) || item.tags.iter().any(|tag| tag.to_lowercase() == query)
+        }).collect()
+    }
+
+    fn is_inside(&self, location: LocationId, ancestor: LocationId) -> bool {
+        let mut current = Some(location);
+
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.location(id).and_then(|location| location.parent);
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inventory, InventoryError, LocationId, LocationKind};
+
+    // Garage (cube 0) / Shelf (cube 1) / Box (cube 2) and a second box on the garage floor (cube 3)
+    fn garage() -> (Inventory, [LocationId; 4]) {
+        let mut inventory = Inventory::new();
+        let garage = inventory.add_location("Garage", LocationKind::Room, None, Some(0), 4).unwrap();
+        let shelf = inventory.add_location("Shelf", LocationKind::Shelf, Some(garage), Some(1), 4).unwrap();
+        let shelf_box = inventory.add_location("Box A", LocationKind::Box, Some(shelf), Some(2), 4).unwrap();
+        let floor_box = inventory.add_location("Box B", LocationKind::Box, Some(garage), Some(3), 4).unwrap();
+        (inventory, [garage, shelf, shelf_box, floor_box])
+    }
+
+    #[test]
+    fn nesting_rules() {
+        let (mut inventory, [garage, shelf, shelf_box, _]) = garage();
+
+        assert!(inventory.add_location("Attic", LocationKind::Room, Some(garage), None, 4).is_ok());
+        assert!(inventory.add_location("Inner box", LocationKind::Box, Some(shelf_box), Some(4), 5).is_ok());
+        assert_eq!(
+            inventory.add_location("Shelf", LocationKind::Shelf, None, None, 5),
+            Err(InventoryError::InvalidParent { kind: LocationKind::Shelf, parent: None })
+        );
+        assert_eq!(
+            inventory.add_location("Shelf", LocationKind::Shelf, Some(shelf), None, 5),
+            Err(InventoryError::InvalidParent { kind: LocationKind::Shelf, parent: Some(LocationKind::Shelf) })
+        );
+        assert_eq!(
+            inventory.add_location("Room", LocationKind::Room, Some(shelf_box), None, 5),
+            Err(InventoryError::InvalidParent { kind: LocationKind::Room, parent: Some(LocationKind::Box) })
+        );
+        assert!(matches!(inventory.add_location("Box", LocationKind::Box, None, Some(4), 5), Err(InventoryError::InvalidParent { .. })));
+        assert!(matches!(inventory.add_location("Box", LocationKind::Box, Some(garage), None, 5), Err(InventoryError::MissingCube(_))));
+        assert_eq!(
+            inventory.add_location("Box", LocationKind::Box, Some(LocationId(99)), Some(4), 5),
+            Err(InventoryError::UnknownLocation(LocationId(99)))
+        );
+    }
+
+    #[test]
+    fn cubes_are_checked() {
+        let (mut inventory, [garage, _, shelf_box, _]) = garage();
+        let locations = inventory.locations.len();
+
+        assert_eq!(inventory.add_location("Box", LocationKind::Box, Some(garage), Some(2), 5), Err(InventoryError::CubeInUse(shelf_box)));
+        assert_eq!(inventory.add_location("Box", LocationKind::Box, Some(garage), Some(5), 5), Err(InventoryError::UnknownCube(5)));
+        assert_eq!(inventory.locations.len(), locations);
+    }
+
+    #[test]
+    fn remove_location_shifts_cubes() {
+        let (mut inventory, [garage, shelf, shelf_box, floor_box]) = garage();
+
+        assert_eq!(inventory.remove_location(shelf).unwrap_err(), InventoryError::NotEmpty(shelf));
+        let item = inventory.add_item(shelf_box, "Screws", 100).unwrap();
+        assert_eq!(inventory.remove_location(shelf_box).unwrap_err(), InventoryError::NotEmpty(shelf_box));
+        inventory.move_item(item, garage).unwrap();
+
+        assert_eq!(inventory.remove_location(shelf_box).unwrap().cube, Some(2));
+        assert_eq!(inventory.location(garage).unwrap().cube, Some(0));
+        assert_eq!(inventory.location(shelf).unwrap().cube, Some(1));
+        assert_eq!(inventory.location(floor_box).unwrap().cube, Some(2));
+        assert_eq!(inventory.remove_location(shelf_box).unwrap_err(), InventoryError::UnknownLocation(shelf_box));
+    }
+
+    #[test]
+    fn cube_removed_shifts_cubes() {
+        let (mut inventory, [garage, shelf, shelf_box, floor_box]) = garage();
+
+        assert_eq!(inventory.cube_removed(2), Err(InventoryError::CubeInUse(shelf_box)));
+        assert_eq!(inventory.location(shelf_box).unwrap().cube, Some(2));
+
+        inventory.cube_removed(1).unwrap();
+        assert_eq!(inventory.location(garage).unwrap().cube, Some(0));
+        assert_eq!(inventory.location(shelf).unwrap().cube, None);
+        assert_eq!(inventory.location(shelf_box).unwrap().cube, Some(1));
+        assert_eq!(inventory.location(floor_box).unwrap().cube, Some(2));
+    }
+
+    #[test]
+    fn path_and_search() {
+        let (mut inventory, [garage, _, shelf_box, floor_box]) = garage();
+        assert_eq!(inventory.path(shelf_box), "Garage / Shelf / Box A");
+        assert_eq!(inventory.path(LocationId(99)), "");
+
+        let screws = inventory.add_item(shelf_box, "Wood screws", 100).unwrap();
+        let drill = inventory.add_item(floor_box, "Drill", 1).unwrap();
+        inventory.item_mut(drill).unwrap().tags.push("Tools".to_string());
+
+        let ids = |items: Vec<&super::Item>| items.iter().map(|item| item.id).collect::<Vec<_>>();
+        assert_eq!(ids(inventory.search("SCREW")), [screws]);
+        assert_eq!(ids(inventory.search("tools")), [drill]);
+        assert_eq!(ids(inventory.search("tool")), []);
+        assert_eq!(ids(inventory.items_below(garage)), [screws, drill]);
+        assert_eq!(ids(inventory.items_below(floor_box)), [drill]);
+    }
+}
@@ -0,0 +1,52 @@
+use iron_oxide::primitives::Vec3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lighting {
+    /// Direction the sun light travels in, world up is +y.
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    pub sun_intensity: f32,
+    pub ambient_color: Vec3,
+    pub ambient_intensity: f32,
+    pub point_lights: Vec<PointLight>,
+    /// Set when anything above changed so the renderer uploads it again.
+    pub changed: bool,
+}
+
+impl Lighting {
+    /// Default sun and ambient plus the warm light of the demo scene.
+    /// Worlds saved before lighting was part of the file were lit like this.
+    pub fn demo() -> Self {
+        let mut lighting = Self::default();
+        lighting.add_point_light(PointLight { position: Vec3::new(1.5, 1.5, -1.5), color: Vec3::new(1.0, 0.8, 0.6), intensity: 2.0, radius: 6.0 });
+        lighting
+    }
+
+    pub fn add_point_light(&mut self, light: PointLight) {
+        self.point_lights.push(light);
+        self.changed = true;
+    }
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::new(-0.4, -1.0, 0.3).normalize(),
+            sun_color: Vec3::new(1.0, 0.96, 0.9),
+            sun_intensity: 1.0,
+            ambient_color: Vec3::new(0.6, 0.7, 0.9),
+            ambient_intensity: 0.25,
+            point_lights: Vec::new(),
+            changed: true,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Error;
+use super::save::{Chunk, Reader, Writer, CUBES};
+use crate::graphics::{MeshId, SamplerPreset, TextureFilter, TextureWrap};
+
+pub type Migration = fn(Vec<Chunk>) -> Result<Vec<Chunk>, Error>;
+
+/// `MIGRATIONS[n]` upgrades the chunks of a version `n + 1` file to version `n + 2`.
+/// Migrations only ever get appended, old entries describe files that already exist on disk.
+pub const MIGRATIONS: [Migration; 4] = [
+    v1_cube_velocity,
+    v2_cube_material,
+    v3_cube_mesh,
+    v4_material_sampler,
+];
+
+fn map_chunk(mut chunks: Vec<Chunk>, tag: [u8; 4], map: impl Fn(&mut Reader, &mut Writer) -> Result<(), Error>) -> Result<Vec<Chunk>, Error> {
+    for chunk in chunks.iter_mut().filter(|chunk| chunk.tag == tag) {
+        let mut reader = Reader::new(&chunk.payload);
+        let mut writer = Writer::default();
+        map(&mut reader, &mut writer)?;
+        chunk.payload = writer.buf;
+    }
+
+    Ok(chunks)
+}
+
+// Material layout from v3 on: base color, texture, roughness.
+// Migrations copy it field by field, `Reader::material` always reads the newest layout.
+fn copy_v3_material(r: &mut Reader, w: &mut Writer) -> Result<(), Error> {
+    w.bytes(r.bytes(16)?);
+    w.option_u32(r.option_u32()?);
+    w.bytes(r.bytes(4)?);
+    Ok(())
+}
+
+// v2 stores the velocity of every cube so objects in motion keep moving after a reload
+fn v1_cube_velocity(chunks: Vec<Chunk>) -> Result<Vec<Chunk>, Error> {
+    map_chunk(chunks, CUBES, |r, w| {
+        let count = r.u32()?;
+        w.u32(count);
+
+        for _ in 0..count {
+            // position, size, mass, position_lock, gravity
+            w.bytes(r.bytes(12 + 12 + 4 + 12 + 1)?);
+            w.bytes(&[0; 12]);
+        }
+
+        Ok(())
+    })
+}
+
+// v3 gives every cube a material, older cubes get the gray default
+fn v2_cube_material(chunks: Vec<Chunk>) -> Result<Vec<Chunk>, Error> {
+    map_chunk(chunks, CUBES, |r, w| {
+        let count = r.u32()?;
+        w.u32(count);
+
+        for _ in 0..count {
+            // position, size, mass, position_lock, gravity, velocity
+            w.bytes(r.bytes(12 + 12 + 4 + 12 + 1 + 12)?);
+            // base color, no texture, roughness
+            for value in [0.8f32, 0.8, 0.8, 1.0] {
+                w.f32(value);
+            }
+            w.option_u32(None);
+            w.f32(0.8);
+        }
+
+        Ok(())
+    })
+}
+
+// v4 lets every object pick a mesh, everything saved before was a cube
+fn v3_cube_mesh(chunks: Vec<Chunk>) -> Result<Vec<Chunk>, Error> {
+    map_chunk(chunks, CUBES, |r, w| {
+        let count = r.u32()?;
+        w.u32(count);
+
+        for _ in 0..count {
+            // position, size, mass, position_lock, gravity, velocity
+            w.bytes(r.bytes(12 + 12 + 4 + 12 + 1 + 12)?);
+            copy_v3_material(r, w)?;
+            w.u32(MeshId::CUBE.0);
+        }
+
+        Ok(())
+    })
+}
+
+// v5 gives materials a sampler preset, older files keep the nearest filtering they were drawn with
+fn v4_material_sampler(chunks: Vec<Chunk>) -> Result<Vec<Chunk>, Error> {
+    map_chunk(chunks, CUBES, |r, w| {
+        let count = r.u32()?;
+        w.u32(count);
+
+        for _ in 0..count {
+            // position, size, mass, position_lock, gravity, velocity
+            w.bytes(r.bytes(12 + 12 + 4 + 12 + 1 + 12)?);
+            copy_v3_material(r, w)?;
+            w.u8(SamplerPreset { filter: TextureFilter::Nearest, wrap: TextureWrap::Clamp }.index() as u8);
+            // mesh
+            w.bytes(r.bytes(4)?);
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use iron_oxide::primitives::Vec3;
+    use crate::game::{inventory::{ItemId, LocationId, LocationKind}, save::{self, SaveData}};
+    use crate::graphics::{Material, MeshId, SamplerPreset, TextureFilter, TextureWrap};
+
+    // Every fixture holds the same world written by the build of that version:
+    // a locked ground plate, a falling box and a garage with a shelf, the box and two items
+    fn load_fixture(version: u32) -> SaveData {
+        let path = format!("{}/fixtures/saves/v{version}.hsw", env!("CARGO_MANIFEST_DIR"));
+        save::load(&path).unwrap_or_else(|err| panic!("failed to load {path}: {err}"))
+    }
+
+    fn assert_vec3(actual: Vec3, expected: [f32; 3]) {
+        assert_eq!([actual.x, actual.y, actual.z], expected);
+    }
+
+    const MIGRATED_SAMPLER: SamplerPreset = SamplerPreset { filter: TextureFilter::Nearest, wrap: TextureWrap::Clamp };
+
+    fn check_common(data: &SaveData) {
+        assert_eq!(data.cubes.len(), 2);
+
+        let ground = &data.cubes[0].rigit_body;
+        assert_vec3(ground.position, [0.0, -1.0, 0.0]);
+        assert_vec3(ground.size, [4.0, 0.1, 4.0]);
+        assert_eq!(ground.mass, 1000000.0);
+        assert_vec3(ground.position_lock, [0.0, 0.0, 0.0]);
+        assert!(!ground.gravity);
+
+        let falling = &data.cubes[1].rigit_body;
+        assert_vec3(falling.position, [1.0, 0.5, -2.0]);
+        assert_vec3(falling.size, [0.6, 0.4, 0.4]);
+        assert_eq!(falling.mass, 1.0);
+        assert_vec3(falling.position_lock, [1.0, 1.0, 1.0]);
+        assert!(falling.gravity);
+
+        assert_vec3(data.camera.position, [2.0, 3.0, 4.0]);
+        assert_eq!((data.camera.yaw, data.camera.pitch, data.camera.fov), (-120.0, -20.0, 60.0));
+        assert_eq!((data.camera.near, data.camera.far), (0.1, 500.0));
+        assert!(data.viewpoints.is_empty());
+        // Lighting was not saved yet, these worlds get the lights they were drawn with
+        assert_eq!(data.lighting.point_lights.len(), 1);
+
+        let inventory = &data.inventory;
+        assert_eq!(inventory.locations.len(), 3);
+        let storage_box = inventory.location(LocationId(2)).unwrap();
+        assert_eq!(storage_box.kind, LocationKind::Box);
+        assert_eq!(storage_box.cube, Some(1));
+        assert_eq!(inventory.path(LocationId(2)), "Garage / Shelf 3 / Box A");
+        assert_eq!(inventory.location(LocationId(1)).unwrap().kind, LocationKind::Shelf);
+
+        let screws = inventory.item(ItemId(0)).unwrap();
+        assert_eq!((screws.name.as_str(), screws.quantity, screws.location), ("Screws", 200, LocationId(2)));
+        assert_eq!(screws.tags, ["hardware", "small"]);
+        assert_eq!(screws.notes, "M4 and M5");
+        assert_eq!(inventory.items_in(LocationId(0)).map(|item| item.name.as_str()).collect::<Vec<_>>(), ["Drill"]);
+    }
+
+    #[test]
+    fn v1_gains_velocity_material_mesh_and_sampler() {
+        let data = load_fixture(1);
+        check_common(&data);
+
+        for cube in &data.cubes {
+            assert_vec3(cube.rigit_body.velocity, [0.0, 0.0, 0.0]);
+            assert_eq!(cube.material, Material { sampler: MIGRATED_SAMPLER, ..Material::DEFAULT });
+            assert_eq!(cube.mesh, MeshId::CUBE);
+        }
+    }
+
+    #[test]
+    fn v2_keeps_velocity() {
+        let data = load_fixture(2);
+        check_common(&data);
+
+        assert_vec3(data.cubes[0].rigit_body.velocity, [0.0, 0.0, 0.0]);
+        assert_vec3(data.cubes[1].rigit_body.velocity, [0.0, -2.0, 0.0]);
+        for cube in &data.cubes {
+            assert_eq!(cube.material, Material { sampler: MIGRATED_SAMPLER, ..Material::DEFAULT });
+            assert_eq!(cube.mesh, MeshId::CUBE);
+        }
+    }
+
+    #[test]
+    fn v3_keeps_materials() {
+        let data = load_fixture(3);
+        check_common(&data);
+
+        assert_vec3(data.cubes[1].rigit_body.velocity, [0.0, -2.0, 0.0]);
+        assert_eq!(data.cubes[0].material, Material { sampler: MIGRATED_SAMPLER, ..Material::CONCRETE });
+        assert_eq!(data.cubes[1].material, Material { sampler: MIGRATED_SAMPLER, ..Material::textured(0) });
+        for cube in &data.cubes {
+            assert_eq!(cube.mesh, MeshId::CUBE);
+        }
+    }
+
+    #[test]
+    fn v4_keeps_meshes() {
+        let data = load_fixture(4);
+        check_common(&data);
+
+        assert_vec3(data.cubes[1].rigit_body.velocity, [0.0, -2.0, 0.0]);
+        assert_eq!(data.cubes[0].material, Material { sampler: MIGRATED_SAMPLER, ..Material::CONCRETE });
+        assert_eq!(data.cubes[1].material, Material { sampler: MIGRATED_SAMPLER, ..Material::textured(0) });
+        assert_eq!(data.cubes[0].mesh, MeshId::PLANE);
+        assert_eq!(data.cubes[1].mesh, MeshId::OPEN_BIN);
+    }
+}
@@ -3,8 +3,16 @@ pub mod states;
 pub mod world;
 pub mod camera;
 pub mod cube;
+pub mod inventory;
+pub mod save;
+pub mod migrations;
+pub mod picking;
+pub mod lighting;
+pub mod frustum;
+pub mod viewpoint;
 
 
 pub use world::World;
-pub use camera::Camera;
-pub use cube::Cube;
\ No newline at end of file
+pub use camera::{Camera, CameraMode, Projection};
+pub use cube::Cube;
+pub use inventory::Inventory;
\ No newline at end of file
@@ -0,0 +1,119 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use iron_oxide::primitives::Vec2;
+use super::{Camera, Cube};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// Builds the world space ray under a cursor position given in window pixels.
+    pub fn from_cursor(camera: &Camera, cursor: Vec2, window_size: Vec2) -> Self {
+        let view_proj = camera.projection(window_size.x / window_size.y) * camera.view_matrix();
+        let inverse = view_proj.invert().unwrap_or(Matrix4::identity());
+
+        // The view matrix uses -y as up, so window y maps onto ndc y without flipping
+        let ndc_x = cursor.x / window_size.x * 2.0 - 1.0;
+        let ndc_y = cursor.y / window_size.y * 2.0 - 1.0;
+
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, z, 1.0);
+            point.truncate() / point.w
+        };
+
+        // `Camera::projection` puts the near plane at depth 0, not at the -1 of plain cgmath
+        let near = unproject(0.0);
+        let far = unproject(1.0);
+
+        Self { origin: near, direction: (far - near).normalize() }
+    }
+
+    /// Slab test against an axis aligned box. Returns the distance along the ray to the entry point,
+    /// or 0 if the origin is inside the box.
+    pub fn intersect_aabb(&self, center: Vector3<f32>, size: Vector3<f32>) -> Option<f32> {
+        let min = center - size * 0.5;
+        let max = center + size * 0.5;
+
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+
+        for axis in 0..3 {
+            let inv_dir = 1.0 / self.direction[axis];
+            let mut t0 = (min[axis] - self.origin[axis]) * inv_dir;
+            let mut t1 = (max[axis] - self.origin[axis]) * inv_dir;
+            if inv_dir < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
+
+/// Returns the index and distance of the nearest cube hit by the ray.
+pub fn pick(ray: &Ray, cubes: &[Cube]) -> Option<(usize, f32)> {
+    cubes.iter().enumerate()
+        .filter_map(|(i, cube)| {
+            let body = &cube.rigit_body;
+            ray.intersect_aabb(body.position.into(), body.size.into()).map(|distance| (i, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+    use iron_oxide::primitives::{Vec2, Vec3};
+    use super::{pick, Ray};
+    use crate::game::{Camera, Cube};
+
+    const WINDOW: Vec2 = Vec2::new(800.0, 600.0);
+    const CENTER: Vec2 = Vec2::new(400.0, 300.0);
+
+    #[test]
+    fn ray_starts_on_the_near_plane() {
+        let camera = Camera::default();
+        let ray = Ray::from_cursor(&camera, CENTER, WINDOW);
+
+        let near = Vector3::from(camera.position) + Vector3::from(camera.front()) * camera.near;
+        assert!((ray.origin - near).magnitude() < 1e-3, "{:?}", ray.origin);
+        assert!((ray.direction - Vector3::from(camera.front())).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn floor_plan_ray_hits_the_floor_below() {
+        let mut camera = Camera::default();
+        camera.toggle_floor_plan();
+
+        let ray = Ray::from_cursor(&camera, CENTER, WINDOW);
+        assert!((ray.origin.y - (camera.position.y - camera.near)).abs() < 1e-3, "{:?}", ray.origin);
+        assert!((ray.direction - Vector3::new(0.0, -1.0, 0.0)).magnitude() < 1e-4);
+
+        // A unit box resting on the ground right under the camera
+        let cubes = [Cube::new(Vec3::new(camera.position.x, 0.5, camera.position.z), Vec3::one(), false)];
+        let (index, distance) = pick(&ray, &cubes).unwrap();
+        assert_eq!(index, 0);
+        assert!((distance - (camera.position.y - camera.near - 1.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn floor_plan_rays_are_parallel() {
+        let mut camera = Camera::default();
+        camera.toggle_floor_plan();
+
+        // At the right edge the ray is half the plan's width to the side, still looking straight down
+        let ray = Ray::from_cursor(&camera, Vec2::new(WINDOW.x, CENTER.y), WINDOW);
+        let offset = ray.origin - Vector3::from(camera.position);
+        let half_width = camera.ortho_height * WINDOW.x / WINDOW.y / 2.0;
+        assert!((Vector3::new(offset.x, 0.0, offset.z).magnitude() - half_width).abs() < 1e-3);
+        assert!((ray.direction - Vector3::new(0.0, -1.0, 0.0)).magnitude() < 1e-4);
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::Path
+};
+use iron_oxide::primitives::Vec3;
+use super::{
+    inventory::{Item, ItemId, LocationId, LocationKind, StorageLocation},
+    lighting::{Lighting, PointLight},
+    migrations::MIGRATIONS,
+    viewpoint::Viewpoint,
+    Camera, Cube, Inventory
+};
+use crate::graphics::{Material, MeshId, SamplerPreset, BUILTIN_TEXTURE_LAYERS};
+
+pub const SAVE_PATH: &str = "world.hsw";
+/// Where a world file that failed to load is moved, so the next save doesn't overwrite it.
+pub const BACKUP_PATH: &str = "world.hsw.bak";
+
+const MAGIC: [u8; 4] = *b"HSVW";
+/// Bump together with a new entry in `migrations::MIGRATIONS`.
+pub const SAVE_VERSION: u32 = 5;
+
+pub const CUBES: [u8; 4] = *b"CUBE";
+pub const CAMERA: [u8; 4] = *b"CAMR";
+pub const INVENTORY: [u8; 4] = *b"INVT";
+/// Optional, files without it simply have no viewpoints.
+pub const VIEWPOINTS: [u8; 4] = *b"VIEW";
+/// Optional, files without it get `Lighting::demo`.
+pub const LIGHTING: [u8; 4] = *b"LITE";
+
+pub struct Chunk {
+    pub tag: [u8; 4],
+    pub payload: Vec<u8>,
+}
+
+pub struct SaveData {
+    pub cubes: Vec<Cube>,
+    pub camera: Camera,
+    pub inventory: Inventory,
+    pub viewpoints: Vec<Viewpoint>,
+    pub lighting: Lighting,
+}
+
+/// File layout: magic, version, then a list of chunks `[tag: 4 bytes][length: u32][payload]`.
+/// All numbers are little endian, strings are a u32 byte length followed by utf8.
+/// Cubes showing an imported mesh are left out, see `saved_cube_index`.
+pub fn save(path: impl AsRef<Path>, cubes: &[Cube], camera: &Camera, inventory: &Inventory, viewpoints: &[Viewpoint], lighting: &Lighting) -> Result<(), Error> {
+    let mut writer = Writer::default();
+    writer.bytes(&MAGIC);
+    writer.u32(SAVE_VERSION);
+
+    writer.chunk(CUBES, |w| {
+        let saved = cubes.iter().filter(|cube| cube.mesh.is_builtin());
+        w.u32(saved.clone().count() as _);
+        for cube in saved {
+            let body = &cube.rigit_body;
+            w.vec3(body.position);
+            w.vec3(body.size);
+            w.f32(body.mass);
+            w.vec3(body.position_lock);
+            w.bool(body.gravity);
+            w.vec3(body.velocity);
+            w.material(&cube.material);
+            w.u32(cube.mesh.0);
+        }
+    });
+
+    writer.chunk(CAMERA, |w| {
+        // A world saved in the floor plan reopens at the view the plan was entered from
+        let (position, yaw, pitch) = camera.previous_view.map_or((camera.position, camera.yaw, camera.pitch), |(_, position, yaw, pitch, _)| (position, yaw, pitch));
+        w.vec3(position);
+        w.f32(yaw);
+        w.f32(pitch);
+        w.f32(camera.fov);
+        w.f32(camera.near);
+        w.f32(camera.far);
+    });
+
+    writer.chunk(INVENTORY, |w| {
+        w.u32(inventory.locations.len() as _);
+        for location in &inventory.locations {
+            w.u32(location.id.0);
+            w.string(&location.name);
+            w.u8(location.kind as u8);
+            w.option_u32(location.parent.map(|parent| parent.0));
+            w.option_u32(location.cube.and_then(|cube| saved_cube_index(cubes, cube)).map(|cube| cube as u32));
+        }
+
+        w.u32(inventory.items.len() as _);
+        for item in &inventory.items {
+            w.u32(item.id.0);
+            w.string(&item.name);
+            w.u32(item.quantity);
+            w.u32(item.tags.len() as _);
+            for tag in &item.tags {
+                w.string(tag);
+            }
+            w.string(&item.notes);
+            w.u32(item.location.0);
+        }
+    });
+
+    writer.chunk(VIEWPOINTS, |w| {
+        w.u32(viewpoints.len() as _);
+        for viewpoint in viewpoints {
+            w.string(&viewpoint.name);
+            w.vec3(viewpoint.position);
+            w.f32(viewpoint.yaw);
+            w.f32(viewpoint.pitch);
+            w.f32(viewpoint.fov);
+        }
+    });
+
+    writer.chunk(LIGHTING, |w| {
+        w.vec3(lighting.sun_direction);
+        w.vec3(lighting.sun_color);
+        w.f32(lighting.sun_intensity);
+        w.vec3(lighting.ambient_color);
+        w.f32(lighting.ambient_intensity);
+        w.u32(lighting.point_lights.len() as _);
+        for light in &lighting.point_lights {
+            w.vec3(light.position);
+            w.vec3(light.color);
+            w.f32(light.intensity);
+            w.f32(light.radius);
+        }
+    });
+
+    // Write next to the old file first so a crash mid-save never leaves a truncated world behind
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, &writer.buf)?;
+    fs::rename(tmp_path, path)
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<SaveData, Error> {
+    let buf = fs::read(path)?;
+    let mut reader = Reader::new(&buf);
+
+    if reader.bytes(4)? != MAGIC {
+        return Err(invalid("not a world file"));
+    }
+
+    let version = reader.u32()?;
+    if version == 0 || version > SAVE_VERSION {
+        return Err(invalid(&format!("unsupported world file version {version}, this build reads up to {SAVE_VERSION}")));
+    }
+
+    let mut chunks = Vec::new();
+    while !reader.is_empty() {
+        let tag = reader.bytes(4)?.try_into().unwrap();
+        let len = reader.u32()? as usize;
+        chunks.push(Chunk { tag, payload: reader.bytes(len)?.to_vec() });
+    }
+
+    // MIGRATIONS[0] upgrades version 1 to 2 and so on
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        chunks = migration(chunks)?;
+    }
+
+    decode(&chunks)
+}
+
+/// Imported meshes are gone after a restart and a later import could reuse the id for a different model.
+/// Their cubes are not saved, so the cubes after them move down in the file.
+fn saved_cube_index(cubes: &[Cube], index: usize) -> Option<usize> {
+    cubes.get(index).filter(|cube| cube.mesh.is_builtin())?;
+    Some(cubes[..index].iter().filter(|cube| cube.mesh.is_builtin()).count())
+}
+
+fn decode(chunks: &[Chunk]) -> Result<SaveData, Error> {
+    let mut data = SaveData { cubes: Vec::new(), camera: Camera::default(), inventory: Inventory::new(), viewpoints: Vec::new(), lighting: Lighting::demo() };
+
+    for chunk in chunks {
+        let mut reader = Reader::new(&chunk.payload);
+
+        match chunk.tag {
+            CUBES => data.cubes = read_cubes(&mut reader)?,
+            CAMERA => data.camera = read_camera(&mut reader)?,
+            INVENTORY => data.inventory = read_inventory(&mut reader)?,
+            VIEWPOINTS => data.viewpoints = read_viewpoints(&mut reader)?,
+            LIGHTING => data.lighting = read_lighting(&mut reader)?,
+            // Unknown chunks are skipped so newer optional data does not break older builds
+            _ => (),
+        }
+    }
+
+    // A damaged or hand edited file could point locations at cubes or parents that do not exist
+    data.inventory.validate(data.cubes.len()).map_err(|err| invalid(&format!("broken inventory: {err}")))?;
+    Ok(data)
+}
+
+fn read_cubes(r: &mut Reader) -> Result<Vec<Cube>, Error> {
+    let count = r.u32()?;
+    let mut cubes = Vec::new();
+
+    for _ in 0..count {
+        let position = r.vec3()?;
+        let size = r.vec3()?;
+        let mass = r.f32()?;
+        let position_lock = r.vec3()?;
+        let gravity = r.bool()?;
+        let velocity = r.vec3()?;
+        let material = r.material()?;
+        // Imported meshes are never saved, an unknown id can only come from a damaged file
+        let mesh = Some(MeshId(r.u32()?)).filter(|mesh| mesh.is_builtin()).unwrap_or(MeshId::CUBE);
+
+        let mut cube = Cube::new(position, size, gravity);
+        cube.rigit_body.mass = mass;
+        cube.rigit_body.position_lock = position_lock;
+        cube.rigit_body.velocity = velocity;
+        cube.material = material;
+        cube.mesh = mesh;
+        cubes.push(cube);
+    }
+
+    Ok(cubes)
+}
+
+fn read_camera(r: &mut Reader) -> Result<Camera, Error> {
+    Ok(Camera {
+        position: r.vec3()?,
+        yaw: r.f32()?,
+        pitch: r.f32()?,
+        fov: r.f32()?,
+        near: r.f32()?,
+        far: r.f32()?,
+        ..Default::default()
+    })
+}
+
+fn read_viewpoints(r: &mut Reader) -> Result<Vec<Viewpoint>, Error> {
+    let count = r.u32()?;
+    let mut viewpoints = Vec::new();
+
+    for _ in 0..count {
+        viewpoints.push(Viewpoint {
+            name: r.string()?,
+            position: r.vec3()?,
+            yaw: r.f32()?,
+            pitch: r.f32()?,
+            fov: r.f32()?,
+        });
+    }
+
+    Ok(viewpoints)
+}
+
+fn read_lighting(r: &mut Reader) -> Result<Lighting, Error> {
+    let mut lighting = Lighting {
+        sun_direction: r.vec3()?,
+        sun_color: r.vec3()?,
+        sun_intensity: r.f32()?,
+        ambient_color: r.vec3()?,
+        ambient_intensity: r.f32()?,
+        ..Default::default()
+    };
+
+    let count = r.u32()?;
+    for _ in 0..count {
+        lighting.add_point_light(PointLight {
+            position: r.vec3()?,
+            color: r.vec3()?,
+            intensity: r.f32()?,
+            radius: r.f32()?,
+        });
+    }
+
+    Ok(lighting)
+}
+
+fn read_inventory(r: &mut Reader) -> Result<Inventory, Error> {
+    let location_count = r.u32()?;
+    let mut locations = Vec::new();
+
+    for _ in 0..location_count {
+        let id = LocationId(r.u32()?);
+        let name = r.string()?;
+        let kind = match r.u8()? {
+            0 => LocationKind::Room,
+            1 => LocationKind::Shelf,
+            2 => LocationKind::Box,
+            kind => return Err(invalid(&format!("unknown location kind {kind}"))),
+        };
+        let parent = r.option_u32()?.map(LocationId);
+        let cube = r.option_u32()?.map(|cube| cube as usize);
+        locations.push(StorageLocation { id, name, kind, parent, cube });
+    }
+
+    let item_count = r.u32()?;
+    let mut items = Vec::new();
+
+    for _ in 0..item_count {
+        let id = ItemId(r.u32()?);
+        let name = r.string()?;
+        let quantity = r.u32()?;
+        let tag_count = r.u32()?;
+        let mut tags = Vec::new();
+        for _ in 0..tag_count {
+            tags.push(r.string()?);
+        }
+        let notes = r.string()?;
+        let location = LocationId(r.u32()?);
+        items.push(Item { id, name, quantity, tags, notes, location });
+    }
+
+    Ok(Inventory::from_parts(locations, items))
+}
+
+/// Runtime loaded textures are not saved, a later session could put a different image on that layer.
+/// Materials that used one fall back to their plain color.
+fn builtin_texture_layer(layer: Option<u32>) -> Option<u32> {
+    layer.filter(|&layer| layer < BUILTIN_TEXTURE_LAYERS)
+}
+
+pub fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Default)]
+pub struct Writer {
+    pub buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.u32(value.len() as _);
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn option_u32(&mut self, value: Option<u32>) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            self.u32(value);
+        }
+    }
+
+    pub fn material(&mut self, material: &Material) {
+        for channel in material.base_color {
+            self.f32(channel);
+        }
+        self.option_u32(builtin_texture_layer(material.texture_layer));
+        self.f32(material.roughness);
+        self.u8(material.sampler.index() as u8);
+    }
+
+    pub fn chunk(&mut self, tag: [u8; 4], content: impl FnOnce(&mut Writer)) {
+        let mut chunk = Writer::default();
+        content(&mut chunk);
+        self.bytes(&tag);
+        self.u32(chunk.buf.len() as _);
+        self.bytes(&chunk.buf);
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buf.len() < len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "world file is truncated"));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn vec3(&mut self) -> Result<Vec3, Error> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    pub fn string(&mut self) -> Result<String, Error> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| invalid("string is not valid utf8"))
+    }
+
+    pub fn option_u32(&mut self) -> Result<Option<u32>, Error> {
+        Ok(if self.bool()? { Some(self.u32()?) } else { None })
+    }
+
+    pub fn material(&mut self) -> Result<Material, Error> {
+        Ok(Material {
+            base_color: [self.f32()?, self.f32()?, self.f32()?, self.f32()?],
+            texture_layer: builtin_texture_layer(self.option_u32()?),
+            roughness: self.f32()?,
+            sampler: {
+                let index = self.u8()?;
+                SamplerPreset::from_index(index as u32).ok_or_else(|| invalid(&format!("unknown sampler preset {index}")))?
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::ErrorKind, path::PathBuf};
+    use iron_oxide::primitives::Vec3;
+    use super::{load, save, Camera, Cube, Inventory, Lighting, SAVE_VERSION};
+    use crate::game::{inventory::{InventoryError, LocationId, LocationKind, StorageLocation}, lighting::PointLight, viewpoint::Viewpoint};
+    use crate::graphics::{Material, MeshId, SamplerPreset, TextureFilter, TextureWrap};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}.hsw", std::process::id()))
+    }
+
+    fn assert_vec3(actual: Vec3, expected: Vec3) {
+        assert_eq!([actual.x, actual.y, actual.z], [expected.x, expected.y, expected.z]);
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let mut falling = Cube::new(Vec3::new(1.0, 0.5, -2.0), Vec3::new(0.6, 0.4, 0.4), true)
+            .with_material(Material { texture_layer: Some(0), sampler: SamplerPreset { filter: TextureFilter::Anisotropic, wrap: TextureWrap::Clamp }, ..Material::CARDBOARD })
+            .with_mesh(MeshId::OPEN_BIN);
+        falling.rigit_body.velocity = Vec3::new(0.0, -2.0, 0.0);
+        let cubes = [Cube::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(4.0, 0.1, 4.0), false).with_material(Material::CONCRETE), falling];
+
+        let camera = Camera { position: Vec3::new(2.0, 3.0, 4.0), yaw: -120.0, pitch: -20.0, fov: 60.0, ..Default::default() };
+
+        let mut inventory = Inventory::new();
+        let garage = inventory.add_location("Garage", LocationKind::Room, None, Some(0), cubes.len()).unwrap();
+        let storage_box = inventory.add_location("Box A", LocationKind::Box, Some(garage), Some(1), cubes.len()).unwrap();
+        let screws = inventory.add_item(storage_box, "Screws", 200).unwrap();
+        inventory.item_mut(screws).unwrap().tags = vec!["hardware".to_string(), "small".to_string()];
+        inventory.item_mut(screws).unwrap().notes = "M4 and M5".to_string();
+
+        let viewpoints = [Viewpoint { name: "Workbench".to_string(), position: Vec3::new(1.0, 1.7, 0.5), yaw: 45.0, pitch: -10.0, fov: 50.0 }];
+
+        let mut lighting = Lighting { sun_intensity: 0.5, ..Default::default() };
+        lighting.add_point_light(PointLight { position: Vec3::new(0.0, 2.5, 0.0), color: Vec3::new(1.0, 0.9, 0.8), intensity: 3.0, radius: 8.0 });
+
+        let path = temp_path("round-trip");
+        save(&path, &cubes, &camera, &inventory, &viewpoints, &lighting).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let data = load(&path);
+        fs::remove_file(&path).unwrap();
+        let data = data.unwrap();
+
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), SAVE_VERSION);
+
+        assert_eq!(data.cubes.len(), cubes.len());
+        for (loaded, saved) in data.cubes.iter().zip(&cubes) {
+            assert_vec3(loaded.rigit_body.position, saved.rigit_body.position);
+            assert_vec3(loaded.rigit_body.size, saved.rigit_body.size);
+            assert_vec3(loaded.rigit_body.velocity, saved.rigit_body.velocity);
+            assert_vec3(loaded.rigit_body.position_lock, saved.rigit_body.position_lock);
+            assert_eq!(loaded.rigit_body.mass, saved.rigit_body.mass);
+            assert_eq!(loaded.rigit_body.gravity, saved.rigit_body.gravity);
+            assert_eq!(loaded.material, saved.material);
+            assert_eq!(loaded.mesh, saved.mesh);
+        }
+
+        assert_vec3(data.camera.position, camera.position);
+        assert_eq!((data.camera.yaw, data.camera.pitch, data.camera.fov), (camera.yaw, camera.pitch, camera.fov));
+        assert_eq!((data.camera.near, data.camera.far), (camera.near, camera.far));
+
+        assert_eq!(data.inventory.path(storage_box), "Garage / Box A");
+        assert_eq!(data.inventory.location(storage_box).unwrap().cube, Some(1));
+        assert_eq!(data.inventory.location(garage).unwrap().cube, Some(0));
+        let loaded_screws = data.inventory.item(screws).unwrap();
+        assert_eq!((loaded_screws.name.as_str(), loaded_screws.quantity, loaded_screws.location), ("Screws", 200, storage_box));
+        assert_eq!(loaded_screws.tags, ["hardware", "small"]);
+        assert_eq!(loaded_screws.notes, "M4 and M5");
+
+        assert_eq!(data.viewpoints.len(), 1);
+        assert_eq!(data.viewpoints[0].name, "Workbench");
+        assert_vec3(data.viewpoints[0].position, viewpoints[0].position);
+        assert_eq!((data.viewpoints[0].yaw, data.viewpoints[0].pitch, data.viewpoints[0].fov), (45.0, -10.0, 50.0));
+
+        assert_eq!(data.lighting.sun_intensity, 0.5);
+        assert_eq!(data.lighting.point_lights.len(), 1);
+        assert_vec3(data.lighting.point_lights[0].position, Vec3::new(0.0, 2.5, 0.0));
+        assert_eq!(data.lighting.point_lights[0].radius, 8.0);
+    }
+
+    #[test]
+    fn imported_objects_are_left_out() {
+        let cubes = [
+            Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), false).with_mesh(MeshId(MeshId::BUILTIN_COUNT)),
+            Cube::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), false).with_mesh(MeshId::SHELF),
+            Cube::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), false).with_mesh(MeshId(MeshId::BUILTIN_COUNT + 1)),
+            Cube::new(Vec3::new(3.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), true),
+        ];
+        let mut inventory = Inventory::new();
+        let garage = inventory.add_location("Garage", LocationKind::Room, None, Some(0), cubes.len()).unwrap();
+        let shelf = inventory.add_location("Shelf", LocationKind::Shelf, Some(garage), Some(1), cubes.len()).unwrap();
+        let storage_box = inventory.add_location("Box A", LocationKind::Box, Some(shelf), Some(3), cubes.len()).unwrap();
+
+        let path = temp_path("imported");
+        save(&path, &cubes, &Camera::default(), &inventory, &[], &Lighting::default()).unwrap();
+        let data = load(&path);
+        fs::remove_file(&path).unwrap();
+        let data = data.unwrap();
+
+        assert_eq!(data.cubes.iter().map(|cube| cube.mesh).collect::<Vec<_>>(), [MeshId::SHELF, MeshId::CUBE]);
+        assert_vec3(data.cubes[1].rigit_body.position, Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(data.inventory.location(garage).unwrap().cube, None);
+        assert_eq!(data.inventory.location(shelf).unwrap().cube, Some(0));
+        assert_eq!(data.inventory.location(storage_box).unwrap().cube, Some(1));
+    }
+
+    #[test]
+    fn inventory_with_missing_cube_is_rejected() {
+        let cubes = [Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), false)];
+        let mut inventory = Inventory::new();
+        let garage = inventory.add_location("Garage", LocationKind::Room, None, None, cubes.len()).unwrap();
+        inventory.locations.push(StorageLocation { id: LocationId(1), name: "Box A".to_string(), kind: LocationKind::Box, parent: Some(garage), cube: Some(3) });
+        assert_eq!(inventory.validate(cubes.len()), Err(InventoryError::UnknownCube(3)));
+
+        let path = temp_path("missing-cube");
+        save(&path, &cubes, &Camera::default(), &inventory, &[], &Lighting::default()).unwrap();
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.err().map(|err| err.kind()), Some(ErrorKind::InvalidData));
+    }
+}
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use iron_oxide::{
     graphics::formats::Color,
     ui::{
@@ -17,8 +18,42 @@ use iron_oxide::{
     }
 };
 
-pub fn build_main() -> UiState {
+/// Viewpoint buttons in the sidebar, further viewpoints are only reachable through their number key.
+pub const VIEWPOINT_SLOTS: usize = 6;
+
+thread_local! {
+    /// Slot clicked since `World::update` last asked.
+    static CLICKED_VIEWPOINT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+pub fn take_clicked_viewpoint() -> Option<usize> {
+    CLICKED_VIEWPOINT.take()
+}
+
+/// Content of the sidebar that changes at runtime, `World` hands out a new one whenever it does.
+#[derive(Debug, Clone, Default)]
+pub struct Sidebar {
+    /// Names of the saved viewpoints, the first `VIEWPOINT_SLOTS` get a button.
+    pub viewpoints: Vec<String>,
+    /// Name typed so far for a viewpoint that is about to be saved.
+    pub viewpoint_draft: Option<String>,
+    /// Lines describing the selected object, empty when nothing is selected.
+    pub details: Vec<String>,
+}
+
+pub fn build_main(sidebar: &Sidebar) -> UiState {
     let mut state = UiState::create(true);
+    let label = |slot: usize| sidebar.viewpoints.get(slot).cloned().unwrap_or_else(|| "-".to_string());
+
+    let mut rows = Vec::new();
+    if let Some(draft) = &sidebar.viewpoint_draft {
+        rows.push(text_row(&state, format!("Name: {draft}_"), 24.0).wrap(&state));
+        rows.push(text_row(&state, "Enter saves, Esc cancels".to_string(), 18.0).wrap(&state));
+    }
+    if !sidebar.details.is_empty() {
+        rows.push(text_row(&state, "Selection".to_string(), 24.0).wrap(&state));
+        rows.extend(sidebar.details.iter().map(|line| text_row(&state, line.clone(), 18.0).wrap(&state)));
+    }
 
     state.add_element(
         AbsoluteLayout {
@@ -45,79 +80,25 @@ pub fn build_main() -> UiState {
                     ],
                     ..Default::default()
                 }.wrap(&state),
-                Button {
-                    margin: OutArea::vertical(Px(1.0)),
-                    width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
-                    childs: vec![
-                        Text { 
-                            text: "Normal".to_string(),
-                            color: Color::RED,
-                            align: Align::Center,
-                            ..Default::default()
-                        }.wrap(&state)
-                    ],
-                    callback: ErasedFnPointer::from_free(on_click),
-                    ..Default::default()
-                }.wrap(&state),
-                Button {
-                    margin: OutArea::vertical(Px(1.0)),
-                    width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
-                    childs: vec![
-                        Text { 
-                            text: "Normal".to_string(),
-                            color: Color::RED,
-                            align: Align::Center,
-                            ..Default::default()
-                        }.wrap(&state)
-                    ],
-                    callback: ErasedFnPointer::from_free(on_click),
-                    ..Default::default()
-                }.wrap(&state),
-                Button {
-                    margin: OutArea::vertical(Px(1.0)),
+                Container {
+                    margin: OutArea::vertical(Px(10.0)),
                     width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
+                    height: Px(24.0),
+                    color: Color::ZERO,
                     childs: vec![
                         Text { 
-                            text: "Normal".to_string(),
+                            text: "Viewpoints".to_string(),
                             color: Color::RED,
                             align: Align::Center,
                             ..Default::default()
                         }.wrap(&state)
                     ],
-                    callback: ErasedFnPointer::from_free(on_click),
-                    ..Default::default()
-                }.wrap(&state),
-                Container {
-                    margin: OutArea::vertical(Px(1.0)),
-                    width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
-                    border: [0.0; 4],
                     ..Default::default()
                 }.wrap(&state),
-                Container {
-                    margin: OutArea::vertical(Px(1.0)),
-                    width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
-                    border: [0.0; 4],
-                    ..Default::default()
-                }.wrap(&state),
-                Container {
-                    margin: OutArea::vertical(Px(1.0)),
-                    width: Relative(1.0),
-                    height: Px(41.0),
-                    color: Color::rgb(35, 35, 35),
-                    border: [0.0; 4],
-                    ..Default::default()
-                }.wrap(&state),
-            ],
+            ].into_iter()
+                .chain((0..VIEWPOINT_SLOTS).map(|slot| viewpoint_button(&state, slot, label(slot)).wrap(&state)))
+                .chain(rows)
+                .collect(),
             ..Default::default()
         }
     );
@@ -125,23 +106,65 @@ pub fn build_main() -> UiState {
     state
 }
 
-fn on_click(context: CallContext) {
+/// One line of text across the sidebar.
+fn text_row(state: &UiState, text: String, height: f32) -> Container {
+    Container {
+        margin: OutArea::vertical(Px(2.0)),
+        width: Relative(1.0),
+        height: Px(height),
+        color: Color::ZERO,
+        childs: vec![
+            Text {
+                text,
+                color: Color::RED,
+                align: Align::Left,
+                ..Default::default()
+            }.wrap(state)
+        ],
+        ..Default::default()
+    }
+}
+
+/// Sidebar entry for `World::viewpoints[slot]`, clicking it flies the camera there.
+fn viewpoint_button(state: &UiState, slot: usize, label: String) -> Button {
+    Button {
+        margin: OutArea::vertical(Px(1.0)),
+        width: Relative(1.0),
+        height: Px(41.0),
+        color: Color::rgb(35, 35, 35),
+        childs: vec![
+            Text { 
+                text: label,
+                color: Color::RED,
+                align: Align::Center,
+                ..Default::default()
+            }.wrap(state)
+        ],
+        callback: ErasedFnPointer::from_free(VIEWPOINT_CLICKS[slot]),
+        ..Default::default()
+    }
+}
+
+// Callbacks are plain function pointers, so every slot needs its own instance
+const VIEWPOINT_CLICKS: [fn(CallContext); VIEWPOINT_SLOTS] = [
+    on_viewpoint_click::<0>,
+    on_viewpoint_click::<1>,
+    on_viewpoint_click::<2>,
+    on_viewpoint_click::<3>,
+    on_viewpoint_click::<4>,
+    on_viewpoint_click::<5>,
+];
+
+fn on_viewpoint_click<const SLOT: usize>(context: CallContext) {
     let button: &mut Button = unsafe { context.element.downcast_mut() };
-    let text: &mut Text = unsafe { button.childs[0].downcast_mut() };
     match button.state {
-        ButtonState::Normal => {
-            button.color = Color::rgb(35, 35, 35);
-            text.set_new("Button");
-        },
-        ButtonState::Hovered => {
-            button.color = Color::rgb(40, 40, 40);
-            text.set_new("Hover");
-        },
+        ButtonState::Normal => button.color = Color::rgb(35, 35, 35),
+        ButtonState::Hovered => button.color = Color::rgb(40, 40, 40),
         ButtonState::Pressed => {
             button.color = Color::rgb(45, 45, 45);
-            text.set_new("Press");
+            CLICKED_VIEWPOINT.set(Some(SLOT));
         },
         ButtonState::Disabled => unreachable!(),
     }
     context.ui.dirty = DirtyFlags::Color;
-}
\ No newline at end of file
+}
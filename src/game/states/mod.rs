@@ -1,2 +1,2 @@
 mod main_state;
-pub use main_state::build_main;
\ No newline at end of file
+pub use main_state::{build_main, take_clicked_viewpoint, Sidebar};
\ No newline at end of file
@@ -0,0 +1,68 @@
+use iron_oxide::primitives::Vec3;
+
+use super::Camera;
+
+/// Seconds a flight between two viewpoints takes.
+pub const FLIGHT_DURATION: f32 = 1.2;
+
+/// Bookmarked camera placement, saved with the world.
+#[derive(Debug, Clone)]
+pub struct Viewpoint {
+    pub name: String,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl Viewpoint {
+    pub fn capture(name: impl Into<String>, camera: &Camera) -> Self {
+        Self { name: name.into(), position: camera.position, yaw: camera.yaw, pitch: camera.pitch, fov: camera.fov }
+    }
+}
+
+/// Eased camera move from wherever the camera was towards a viewpoint.
+#[derive(Debug, Clone)]
+pub struct CameraFlight {
+    from: Viewpoint,
+    to: Viewpoint,
+    elapsed: f32,
+}
+
+impl CameraFlight {
+    pub fn new(camera: &Camera, to: Viewpoint) -> Self {
+        Self { from: Viewpoint::capture("", camera), to, elapsed: 0.0 }
+    }
+
+    /// Moves the camera along the flight, returns false once it arrived.
+    pub fn advance(&mut self, camera: &mut Camera, delta_time: f32) -> bool {
+        self.elapsed = (self.elapsed + delta_time).min(FLIGHT_DURATION);
+        let t = ease_in_out(self.elapsed / FLIGHT_DURATION);
+        let (from, to) = (&self.from, &self.to);
+
+        // Yaw turns the short way round, 350° to 10° is 20° and not 340°
+        let yaw_delta = (to.yaw - from.yaw + 180.0).rem_euclid(360.0) - 180.0;
+
+        camera.set_view(
+            from.position + (to.position - from.position) * t,
+            from.yaw + yaw_delta * t,
+            lerp(from.pitch, to.pitch, t),
+            lerp(from.fov, to.fov, t),
+        );
+
+        self.elapsed < FLIGHT_DURATION
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Cubic ease in and out, starts and stops without a jolt.
+fn ease_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
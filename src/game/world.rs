@@ -1,76 +1,384 @@
-use std::{cell::RefCell, rc::Rc};
-use cgmath::Matrix4;
-use iron_oxide::{graphics::SinlgeTimeCommands, physics::System, primitives::Vec3, ui::UiState};
-use crate::graphics::VulkanRender;
-use super::{Camera, Cube};
+use std::{cell::RefCell, fs, io::{Error, ErrorKind}, path::Path, rc::Rc};
+use iron_oxide::{physics::System, primitives::{Vec2, Vec3}, ui::UiState};
+use crate::graphics::{Material, MeshBatch, MeshRegistry, Model, ModelError, ModelMaterial, TextureRegistry, WorldInstance, INSTANCE_HOVERED, INSTANCE_SELECTED};
+use super::{
+    frustum::Frustum,
+    inventory::{InventoryError, LocationId, LocationKind},
+    lighting::Lighting,
+    picking::{self, Ray},
+    save,
+    states::{take_clicked_viewpoint, Sidebar},
+    camera::snap_to_grid,
+    viewpoint::{CameraFlight, Viewpoint},
+    Camera, CameraMode, Cube, Inventory
+};
 
 #[repr(C)]
 #[derive()]
 pub struct World {
-    pub renderer: Rc<RefCell<VulkanRender>>,
     pub camera: Camera,
     pub movement_vector: Vec3,
     pub ui: Rc<RefCell<UiState>>,
     pub system: System,
     pub cubes: Vec<Cube>,
+    pub meshes: MeshRegistry,
+    pub textures: TextureRegistry,
+    pub inventory: Inventory,
+    pub lighting: Lighting,
+    pub selected: Option<usize>,
+    pub hovered: Option<usize>,
+    pub viewpoints: Vec<Viewpoint>,
+    /// Name being typed for the next viewpoint, see `start_viewpoint_name`.
+    pub viewpoint_draft: Option<String>,
+    flight: Option<CameraFlight>,
+    /// Set when a broken world file couldn't be moved aside, saving would destroy it.
+    read_only: bool,
+    /// Set when the sidebar shows stale content, see `take_sidebar`.
+    sidebar_changed: bool,
 }
 
 impl World {
-    pub fn create(renderer: Rc<RefCell<VulkanRender>>, ui: Rc<RefCell<UiState>>) -> Self {
-        let mut cube = Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), true);
+    pub fn create(ui: Rc<RefCell<UiState>>) -> Self {
+        match save::load(save::SAVE_PATH) {
+            Ok(data) => {
+                let mut world = Self::new(ui, data.cubes, data.camera);
+                world.inventory = data.inventory;
+                world.viewpoints = data.viewpoints;
+                world.lighting = data.lighting;
+                return world;
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => {
+                log::error!("failed to load {}: {err}", save::SAVE_PATH);
+
+                // Keep the file around for a newer build or a manual rescue instead of autosaving the demo scene over it
+                let mut world = Self::create_default(ui);
+                match fs::rename(save::SAVE_PATH, save::BACKUP_PATH) {
+                    Ok(()) => log::warn!("moved {} to {}", save::SAVE_PATH, save::BACKUP_PATH),
+                    Err(err) => {
+                        log::error!("failed to move {} aside, saving is disabled: {err}", save::SAVE_PATH);
+                        world.read_only = true;
+                    },
+                }
+                return world;
+            },
+        }
+
+        Self::create_default(ui)
+    }
+
+    fn create_default(ui: Rc<RefCell<UiState>>) -> Self {
+        let mut cube = Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), true).with_material(Material::CARDBOARD);
         cube.rigit_body.velocity.y = 10.0;
-        let mut plane = Cube::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(2.0, 0.1, 2.0), false);
-        let mut plane2 = Cube::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(2.0, 0.1, 2.0), true);
+        let mut plane = Cube::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(2.0, 0.1, 2.0), false).with_material(Material::CONCRETE);
+        let mut plane2 = Cube::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(2.0, 0.1, 2.0), true).with_material(Material::WOOD);
         plane.rigit_body.position_lock = Vec3::zero();
         plane.rigit_body.mass = 1000000.0;
         plane2.rigit_body.mass = 2.0;
-        let system = System::new();
 
         let cubes =  vec![plane, cube, plane2];
 
+        let mut world = Self::new(ui, cubes, Camera::default());
+        world.lighting = Lighting::demo();
+        let cube_count = world.cubes.len();
+        let home = world.inventory.add_location("Home", LocationKind::Room, None, None, cube_count).unwrap();
+        world.inventory.add_location("Box", LocationKind::Box, Some(home), Some(1), cube_count).unwrap();
+        world
+    }
+
+    pub fn new(ui: Rc<RefCell<UiState>>, cubes: Vec<Cube>, camera: Camera) -> Self {
         Self {
-            camera: Camera::default(),
+            camera,
             movement_vector: Vec3::default(),
             ui,
-            system,
+            system: System::new(),
             cubes,
-            renderer,
+            meshes: MeshRegistry::new(),
+            textures: TextureRegistry::new(),
+            inventory: Inventory::new(),
+            lighting: Lighting::default(),
+            selected: None,
+            hovered: None,
+            viewpoints: Vec::new(),
+            viewpoint_draft: None,
+            flight: None,
+            read_only: false,
+            sidebar_changed: false,
         }
     }
 
-    pub fn get_instances(&self) -> Vec<Matrix4<f32>> {
-        let mut instances = Vec::with_capacity(self.cubes.len());
-        
-        for cube in &self.cubes {
-            instances.push(cube.get_instance());
+    pub fn save(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::other(format!("{} could not be loaded or moved aside", save::SAVE_PATH)));
         }
+        let imported = self.cubes.iter().filter(|cube| !cube.mesh.is_builtin()).count();
+        if imported > 0 {
+            log::warn!("{imported} imported objects are not saved, import their models again after a restart");
+        }
+        save::save(save::SAVE_PATH, &self.cubes, &self.camera, &self.inventory, &self.viewpoints, &self.lighting)
+    }
 
-        instances
+    /// Index of the nearest cube under the cursor.
+    pub fn pick(&self, cursor: Vec2, window_size: Vec2) -> Option<usize> {
+        let ray = Ray::from_cursor(&self.camera, cursor, window_size);
+        picking::pick(&ray, &self.cubes).map(|(index, _)| index)
     }
 
-    pub fn update(&mut self, delta_time: f32, renderer: &mut VulkanRender) {
-        if delta_time > 0.1 {
-            return;
+    /// Grid snapped point on top of whatever is under the cursor, or on the ground plane y = 0 where nothing is.
+    /// Used to place objects in the floor plan.
+    pub fn floor_point(&self, cursor: Vec2, window_size: Vec2) -> Option<Vec3> {
+        let ray = Ray::from_cursor(&self.camera, cursor, window_size);
+        let distance = match picking::pick(&ray, &self.cubes) {
+            Some((_, distance)) => distance,
+            None if ray.direction.y.abs() > f32::EPSILON => -ray.origin.y / ray.direction.y,
+            None => return None,
+        };
+        if distance < 0.0 {
+            return None;
         }
-        self.system.update(&mut self.cubes, delta_time);
-        
-        if self.movement_vector != Vec3::zero() {
-            self.camera.process_movement(self.movement_vector, 0.5);
+
+        let hit = ray.origin + ray.direction * distance;
+        Some(snap_to_grid(Vec3::new(hit.x, hit.y, hit.z)))
+    }
+
+    pub fn select(&mut self, selected: Option<usize>) {
+        self.selected = selected;
+        self.sidebar_changed = true;
+
+        if let Some(index) = selected {
+            self.camera.recenter(self.cubes[index].rigit_body.position);
         }
+    }
 
-        let instances = self.get_instances();
+    /// Sidebar content for the current state of the world.
+    pub fn sidebar(&self) -> Sidebar {
+        Sidebar {
+            viewpoints: self.viewpoints.iter().map(|viewpoint| viewpoint.name.clone()).collect(),
+            viewpoint_draft: self.viewpoint_draft.clone(),
+            details: self.selection_details(),
+        }
+    }
+
+    /// New sidebar content if anything shown in it changed since the last call.
+    pub fn take_sidebar(&mut self) -> Option<Sidebar> {
+        std::mem::take(&mut self.sidebar_changed).then(|| self.sidebar())
+    }
+
+    /// Name, placement and contents of the selected object, as shown in the sidebar.
+    fn selection_details(&self) -> Vec<String> {
+        const MAX_ITEM_LINES: usize = 8;
+
+        let Some(index) = self.selected else { return Vec::new() };
+        let body = &self.cubes[index].rigit_body;
+        let vec3 = |value: Vec3| format!("{:.2}, {:.2}, {:.2}", value.x, value.y, value.z);
 
-        let buffer_size = size_of::<Matrix4<f32>>() as u64 * instances.len() as u64;
+        let mut lines = Vec::new();
+        let location = self.inventory.location_for_cube(index);
+        match location {
+            Some(location) => {
+                lines.push(self.inventory.path(location.id));
+                lines.push(format!("{:?}", location.kind));
+            },
+            None => lines.push(format!("Cube {index}")),
+        }
+        lines.push(format!("Position {}", vec3(body.position)));
+        lines.push(format!("Size {}", vec3(body.size)));
+
+        if let Some(location) = location {
+            let items: Vec<_> = self.inventory.items_in(location.id).collect();
+            if items.is_empty() {
+                lines.push("No items".to_string());
+            }
+            lines.extend(items.iter().take(MAX_ITEM_LINES).map(|item| format!("{}x {}", item.quantity, item.name)));
+            if items.len() > MAX_ITEM_LINES {
+                lines.push(format!("and {} more", items.len() - MAX_ITEM_LINES));
+            }
+        }
+
+        lines
+    }
 
-        let mapped_memory = renderer.staging_buffer.map_memory(&renderer.base.device, buffer_size, 0);
-        unsafe {
-            std::ptr::copy_nonoverlapping(instances.as_ptr() as *const u8, mapped_memory as _, buffer_size as usize);
-            renderer.staging_buffer.unmap_memory(&renderer.base.device);
+    /// Bookmarks the current camera, a viewpoint with the same name is replaced.
+    pub fn save_viewpoint(&mut self, name: impl Into<String>) {
+        let viewpoint = Viewpoint::capture(name, &self.camera);
+        match self.viewpoints.iter_mut().find(|existing| existing.name == viewpoint.name) {
+            Some(existing) => *existing = viewpoint,
+            None => self.viewpoints.push(viewpoint),
+        }
+        self.sidebar_changed = true;
+    }
+
+    /// Starts naming a viewpoint of the current camera, the name is typed into the sidebar.
+    pub fn start_viewpoint_name(&mut self) {
+        self.viewpoint_draft = Some(String::new());
+        self.sidebar_changed = true;
+    }
+
+    pub fn type_viewpoint_name(&mut self, text: &str) {
+        if let Some(draft) = &mut self.viewpoint_draft {
+            draft.extend(text.chars().filter(|c| !c.is_control()));
+            self.sidebar_changed = true;
+        }
+    }
+
+    pub fn erase_viewpoint_name(&mut self) {
+        if let Some(draft) = &mut self.viewpoint_draft {
+            draft.pop();
+            self.sidebar_changed = true;
+        }
+    }
+
+    /// Saves the viewpoint under the typed name, or a numbered one if nothing was typed.
+    pub fn finish_viewpoint_name(&mut self) {
+        let Some(draft) = self.viewpoint_draft.take() else { return };
+        let name = match draft.trim() {
+            "" => format!("Viewpoint {}", self.viewpoints.len() + 1),
+            name => name.to_string(),
+        };
+        log::info!("saved viewpoint {name}");
+        self.save_viewpoint(name);
+    }
+
+    pub fn cancel_viewpoint_name(&mut self) {
+        self.viewpoint_draft = None;
+        self.sidebar_changed = true;
+    }
+
+    /// Starts flying the camera to a viewpoint, replacing any flight still underway.
+    pub fn fly_to(&mut self, index: usize) {
+        if let Some(viewpoint) = self.viewpoints.get(index) {
+            // Viewpoints are perspective views, the flight starts from where the floor plan was entered
+            if self.camera.mode == CameraMode::FloorPlan {
+                self.camera.toggle_floor_plan();
+            }
+            self.flight = Some(CameraFlight::new(&self.camera, viewpoint.clone()));
+        }
+    }
+
+    /// Adds a storage box and the cube that represents it in the scene.
+    pub fn add_box(&mut self, parent: LocationId, name: impl Into<String>, position: Vec3, size: Vec3, material: Material) -> Result<LocationId, InventoryError> {
+        self.cubes.push(Cube::new(position, size, true).with_material(material));
+        let cube = self.cubes.len() - 1;
+        let id = self.inventory.add_location(name, LocationKind::Box, Some(parent), Some(cube), self.cubes.len());
+        if id.is_err() {
+            self.cubes.pop();
+        }
+        id
+    }
+
+    /// Loads a glTF or OBJ model at its real size, one object per material.
+    /// `position` is the center of the model, or with `on_floor` the point its bottom rests on.
+    /// Imported objects are not saved, they are gone after a restart.
+    pub fn import_model(&mut self, path: impl AsRef<Path>, position: Vec3, on_floor: bool) -> Result<(), ModelError> {
+        let path = path.as_ref();
+        let mut model = Model::load(path)?;
+        let size = model.fit_unit_cube();
+        let size = Vec3::new(size.x, size.y, size.z);
+        let position = if on_floor { position + Vec3::new(0.0, size.y / 2.0, 0.0) } else { position };
+
+        for mesh in std::mem::take(&mut model.meshes) {
+            let ModelMaterial { mut material, texture } = model.material(&mesh);
+            if let Some(texture) = texture {
+                material.texture_layer = self.textures.add(texture.clone());
+                if material.texture_layer.is_none() {
+                    log::warn!("{}: no texture layer left for {texture}", path.display());
+                }
+            }
+
+            let id = self.meshes.add((mesh.vertices, mesh.indices));
+            // The parts overlap, locked in place they do not push each other apart
+            let mut cube = Cube::new(position, size, false).with_material(material).with_mesh(id);
+            cube.rigit_body.position_lock = Vec3::zero();
+            self.cubes.push(cube);
+        }
+
+        log::info!("imported {} at {position:?}, size {size:?}", path.display());
+        log::warn!("{} is not saved with the world, import it again after a restart", path.display());
+        Ok(())
+    }
+
+    /// Removes an empty storage location together with its cube.
+    pub fn remove_location(&mut self, id: LocationId) -> Result<(), InventoryError> {
+        let location = self.inventory.remove_location(id)?;
+        if let Some(cube) = location.cube {
+            self.cubes.remove(cube);
+            self.selected = None;
+            self.hovered = None;
+            self.sidebar_changed = true;
+        }
+        Ok(())
+    }
+
+    /// Instances inside at least one of `frusta`, sorted by mesh and sampler, together with one batch per combination in use.
+    /// A batch never mixes samplers, so the fragment shader picks the same one for the whole draw.
+    pub fn get_instances(&self, frusta: &[Frustum]) -> (Vec<WorldInstance>, Vec<MeshBatch>) {
+        let batch_key = |cube: &Cube| (cube.mesh, cube.material.sampler);
+        let visible = |cube: &Cube| {
+            let body = &cube.rigit_body;
+            frusta.iter().any(|frustum| frustum.intersects_aabb(body.position.into(), body.size.into()))
         };
 
-        let cmd_buf = SinlgeTimeCommands::begin(&renderer.base, renderer.command_pool);
-        renderer.staging_buffer.copy(&renderer.base, &renderer.instance_buffer, buffer_size, 0, cmd_buf);
-        SinlgeTimeCommands::end(&renderer.base, renderer.command_pool, cmd_buf);
+        let mut order: Vec<usize> = (0..self.cubes.len()).filter(|&i| visible(&self.cubes[i])).collect();
+        order.sort_by_key(|&i| batch_key(&self.cubes[i]));
+
+        let mut instances = Vec::with_capacity(order.len());
+        let mut batches: Vec<MeshBatch> = Vec::new();
+        let mut last_key = None;
+
+        for i in order {
+            let cube = &self.cubes[i];
+            let mut flags = 0;
+            if self.selected == Some(i) {
+                flags |= INSTANCE_SELECTED;
+            }
+            if self.hovered == Some(i) {
+                flags |= INSTANCE_HOVERED;
+            }
+
+            match batches.last_mut() {
+                Some(batch) if last_key == Some(batch_key(cube)) => batch.instance_count += 1,
+                _ => batches.push(MeshBatch { mesh: cube.mesh, first_instance: instances.len() as _, instance_count: 1 }),
+            }
+            last_key = Some(batch_key(cube));
+
+            instances.push(WorldInstance {
+                model: cube.get_instance(),
+                base_color: cube.material.base_color,
+                flags,
+                texture_layer: cube.material.shader_texture_layer(),
+                roughness: cube.material.roughness,
+                sampler: cube.material.sampler.index(),
+            });
+        }
+
+        (instances, batches)
+    }
+
+    /// Advances the simulation. The renderer pulls the new instances itself in `draw_frame`.
+    /// `frame_time` is the real time since the last frame, the simulation runs `sim_speed` times as fast.
+    /// The camera always moves in real time, so it keeps responding while the simulation is paused.
+    pub fn update(&mut self, frame_time: f32, sim_speed: f32) {
+        let delta_time = sim_speed * frame_time;
+        // A hitch would move the objects too far in one step, the simulation skips it
+        if delta_time <= 0.1 {
+            self.system.update(&mut self.cubes, delta_time);
+        }
+        
+        if let Some(index) = take_clicked_viewpoint() {
+            self.fly_to(index);
+        }
+        
+        if self.movement_vector != Vec3::zero() {
+            // Steering by hand ends a flight
+            self.flight = None;
+            self.camera.process_movement(self.movement_vector, 0.5);
+        }
+        if let Some(flight) = &mut self.flight {
+            if !flight.advance(&mut self.camera, frame_time) {
+                self.flight = None;
+            }
+        }
+        self.camera.update(frame_time);
     }
 
 }
\ No newline at end of file
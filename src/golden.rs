@@ -0,0 +1,234 @@
+//! Golden image tests of the world renderer.
+//!
+//! `cargo run -- --golden` renders every scene headless and compares it with `golden/<scene>.png`,
+//! `cargo run -- --golden --bless` (re)writes the references after an intended visual change.
+//! The same check runs as the ignored `golden_scenes` test, CI runs it with `cargo test -- --ignored`
+//! on a runner with a Vulkan device (lavapipe works). Failures leave `<scene>.actual.png` and
+//! `<scene>.diff.png` in `target/golden`.
+
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    io::Error,
+    path::{Path, PathBuf},
+    rc::Rc
+};
+use iron_oxide::{primitives::Vec3, ui::DirtyFlags};
+use winit::dpi::PhysicalSize;
+use crate::{game::{states::{build_main, Sidebar}, Camera, Cube, World}, graphics::{write_png, Material, VulkanRender}};
+
+const REFERENCE_DIR: &str = "golden";
+const OUTPUT_DIR: &str = "target/golden";
+const SIZE: PhysicalSize<u32> = PhysicalSize { width: 320, height: 240 };
+
+// Software rasterizers and GPUs disagree slightly on edges and rounding
+const TOLERANCE: u8 = 3;
+const MAX_MISMATCHED_PIXELS: usize = 0;
+
+struct GoldenScene {
+    name: &'static str,
+    cubes: fn() -> Vec<Cube>,
+    camera: fn() -> Camera,
+}
+
+const SCENES: [GoldenScene; 3] = [
+    GoldenScene { name: "single_cube", cubes: single_cube, camera: front_camera },
+    GoldenScene { name: "stacked_boxes", cubes: stacked_boxes, camera: front_camera },
+    GoldenScene { name: "stacked_boxes_above", cubes: stacked_boxes, camera: above_camera },
+];
+
+fn single_cube() -> Vec<Cube> {
+    vec![Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), false)]
+}
+
+fn stacked_boxes() -> Vec<Cube> {
+    vec![
+        Cube::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(4.0, 0.1, 4.0), false).with_material(Material::CONCRETE),
+        Cube::new(Vec3::new(-0.75, -0.45, 0.0), Vec3::new(1.0, 1.0, 1.0), false).with_material(Material::CARDBOARD),
+        Cube::new(Vec3::new(0.75, -0.7, 0.5), Vec3::new(1.0, 0.5, 0.8), false).with_material(Material::PLASTIC),
+        Cube::new(Vec3::new(-0.75, 0.3, 0.0), Vec3::new(0.6, 0.5, 0.6), false).with_material(Material::WOOD),
+    ]
+}
+
+fn front_camera() -> Camera {
+    Camera { position: Vec3::new(0.0, 0.0, -6.0), ..Default::default() }
+}
+
+fn above_camera() -> Camera {
+    Camera { position: Vec3::new(0.0, -4.0, -5.0), pitch: -40.0, ..Default::default() }
+}
+
+pub struct ImageDiff {
+    pub mismatched: usize,
+    pub max_delta: u8,
+    pub image: Vec<u8>,
+}
+
+/// Compares two RGBA8 images of equal size channel by channel.
+/// The diff image shows mismatching pixels in red and everything else as a dimmed grayscale of `actual`.
+pub fn compare(actual: &[u8], expected: &[u8], tolerance: u8) -> ImageDiff {
+    let mut diff = ImageDiff { mismatched: 0, max_delta: 0, image: vec![0; actual.len()] };
+
+    for (i, (a, e)) in actual.chunks_exact(4).zip(expected.chunks_exact(4)).enumerate() {
+        let delta = a.iter().zip(e).map(|(a, e)| a.abs_diff(*e)).max().unwrap();
+        diff.max_delta = diff.max_delta.max(delta);
+
+        let pixel = if delta > tolerance {
+            diff.mismatched += 1;
+            [255, 0, 0, 255]
+        } else {
+            let gray = ((a[0] as u32 + a[1] as u32 + a[2] as u32) / 12) as u8;
+            [gray, gray, gray, 255]
+        };
+        diff.image[i * 4..i * 4 + 4].copy_from_slice(&pixel);
+    }
+
+    diff
+}
+
+fn read_png(path: &Path) -> Result<(u32, u32, Vec<u8>), Error> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(Error::other(format!("{} is not an RGBA8 png", path.display())));
+    }
+    buf.truncate(info.buffer_size());
+    Ok((info.width, info.height, buf))
+}
+
+fn render_scene(scene: &GoldenScene) -> Vec<u8> {
+    let ui = Rc::new(RefCell::new(build_main(&Sidebar::default())));
+    {
+        let mut ui = ui.borrow_mut();
+        ui.visible = false;
+        ui.dirty = DirtyFlags::Size;
+    }
+
+    // The renderer keeps a pointer to the world, which stays in place until the renderer is destroyed
+    let world = World::new(ui, (scene.cubes)(), (scene.camera)());
+    let mut renderer = VulkanRender::create_headless(SIZE, &world);
+    let pixels = renderer.render_offscreen();
+    unsafe { renderer.base.device.device_wait_idle().unwrap() };
+    renderer.destroy();
+
+    pixels
+}
+
+/// Renders every golden scene headless and compares it with the png in `golden/`.
+/// With `bless` the references are (re)written instead. Returns false if any scene failed.
+pub fn run(bless: bool) -> bool {
+    fs::create_dir_all(OUTPUT_DIR).unwrap();
+    let mut passed = true;
+
+    for scene in &SCENES {
+        let actual = render_scene(scene);
+        let reference_path = PathBuf::from(format!("{REFERENCE_DIR}/{}.png", scene.name));
+
+        if bless {
+            fs::create_dir_all(REFERENCE_DIR).unwrap();
+            write_png(&reference_path, SIZE.width, SIZE.height, &actual).unwrap();
+            println!("golden {}: blessed", scene.name);
+            continue;
+        }
+
+        let expected = match read_png(&reference_path) {
+            Ok((width, height, pixels)) if width == SIZE.width && height == SIZE.height => pixels,
+            Ok((width, height, _)) => {
+                println!("golden {}: FAILED, reference is {width}x{height}, expected {}x{}", scene.name, SIZE.width, SIZE.height);
+                passed = false;
+                continue;
+            },
+            Err(err) => {
+                println!("golden {}: FAILED, cannot read {} ({err}), run with --bless to create it", scene.name, reference_path.display());
+                passed = false;
+                continue;
+            }
+        };
+
+        let diff = compare(&actual, &expected, TOLERANCE);
+        if diff.mismatched > MAX_MISMATCHED_PIXELS {
+            let actual_path = format!("{OUTPUT_DIR}/{}.actual.png", scene.name);
+            let diff_path = format!("{OUTPUT_DIR}/{}.diff.png", scene.name);
+            write_png(Path::new(&actual_path), SIZE.width, SIZE.height, &actual).unwrap();
+            write_png(Path::new(&diff_path), SIZE.width, SIZE.height, &diff.image).unwrap();
+            println!("golden {}: FAILED, {} pixels differ (max delta {}), see {diff_path}", scene.name, diff.mismatched, diff.max_delta);
+            passed = false;
+        } else {
+            println!("golden {}: ok", scene.name);
+        }
+    }
+
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let pixels = image(&[[10, 20, 30, 255], [200, 100, 0, 255]]);
+        let diff = compare(&pixels, &pixels, 0);
+
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.max_delta, 0);
+    }
+
+    #[test]
+    fn deltas_within_tolerance_match() {
+        let actual = image(&[[10, 20, 30, 255], [200, 100, 0, 255]]);
+        let expected = image(&[[13, 20, 28, 255], [200, 97, 0, 252]]);
+        let diff = compare(&actual, &expected, TOLERANCE);
+
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.max_delta, 3);
+    }
+
+    #[test]
+    fn deltas_above_tolerance_mismatch() {
+        let actual = image(&[[10, 20, 30, 255], [200, 100, 0, 255], [0, 0, 0, 255]]);
+        let expected = image(&[[10, 20, 30, 255], [200, 104, 0, 255], [0, 0, 0, 0]]);
+        let diff = compare(&actual, &expected, TOLERANCE);
+
+        assert_eq!(diff.mismatched, 2);
+        assert_eq!(diff.max_delta, 255);
+    }
+
+    #[test]
+    fn diff_image_marks_mismatches_red() {
+        let actual = image(&[[60, 120, 180, 255], [0, 0, 0, 255]]);
+        let expected = image(&[[60, 120, 180, 255], [50, 0, 0, 255]]);
+        let diff = compare(&actual, &expected, TOLERANCE);
+
+        // Matching pixels are a dimmed gray of the actual color: (60 + 120 + 180) / 12
+        assert_eq!(diff.image, image(&[[30, 30, 30, 255], [255, 0, 0, 255]]));
+    }
+
+    #[test]
+    fn diff_image_survives_png_round_trip() {
+        let actual = image(&[[60, 120, 180, 255], [0, 0, 0, 255], [1, 2, 3, 255], [255, 255, 255, 255]]);
+        let expected = image(&[[60, 120, 180, 255], [50, 0, 0, 255], [1, 2, 3, 255], [0, 255, 255, 255]]);
+        let diff = compare(&actual, &expected, TOLERANCE);
+
+        let path = std::env::temp_dir().join(format!("golden_diff_{}.png", std::process::id()));
+        write_png(&path, 2, 2, &diff.image).unwrap();
+        let (width, height, pixels) = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels, diff.image);
+    }
+
+    /// Needs a Vulkan device and the blessed references in `golden/`.
+    #[test]
+    #[ignore]
+    fn golden_scenes() {
+        assert!(run(false), "golden images differ, see target/golden");
+    }
+}
@@ -0,0 +1,87 @@
+use ash::vk::{self, Format, ImageUsageFlags, MemoryPropertyFlags};
+use iron_oxide::graphics::VkBase;
+
+/// Render target image that only lives on the GPU.
+/// Unlike `graphics::Image` it can be multisampled, which the MSAA color and depth buffers need.
+pub struct Attachment {
+    pub inner: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl Attachment {
+    pub fn create(base: &VkBase, extent: vk::Extent2D, format: Format, usage: ImageUsageFlags, samples: vk::SampleCountFlags, aspect_mask: vk::ImageAspectFlags) -> Self {
+        let device = &base.device;
+
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+            mip_levels: 1,
+            array_layers: 1,
+            samples,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+
+        let inner = unsafe { device.create_image(&image_info, None).unwrap() };
+        let requirements = unsafe { device.get_image_memory_requirements(inner) };
+
+        let alloc_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index: find_memory_type(base, requirements.memory_type_bits, MemoryPropertyFlags::DEVICE_LOCAL),
+            ..Default::default()
+        };
+
+        let memory = unsafe { device.allocate_memory(&alloc_info, None).unwrap() };
+        unsafe { device.bind_image_memory(inner, memory, 0).unwrap() };
+
+        let view_info = vk::ImageViewCreateInfo {
+            image: inner,
+            view_type: vk::ImageViewType::TYPE_2D,
+            format,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()
+        };
+
+        let view = unsafe { device.create_image_view(&view_info, None).unwrap() };
+
+        Self { inner, memory, view, samples }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.inner, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub(super) fn find_memory_type(base: &VkBase, type_bits: u32, properties: MemoryPropertyFlags) -> u32 {
+    let memory_properties = unsafe { base.instance.get_physical_device_memory_properties(base.physical_device) };
+
+    (0..memory_properties.memory_type_count).find(|&i| {
+        type_bits & (1 << i) != 0 && memory_properties.memory_types[i as usize].property_flags.contains(properties)
+    }).expect("no suitable memory type")
+}
+
+/// Highest sample count up to `requested` that the device supports for both color and depth attachments.
+pub fn supported_samples(base: &VkBase, requested: u32) -> vk::SampleCountFlags {
+    let limits = unsafe { base.instance.get_physical_device_properties(base.physical_device).limits };
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    [vk::SampleCountFlags::TYPE_8, vk::SampleCountFlags::TYPE_4, vk::SampleCountFlags::TYPE_2].into_iter()
+        .find(|samples| samples.as_raw() <= requested && supported.contains(*samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
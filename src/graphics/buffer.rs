@@ -3,10 +3,10 @@ use std::{ffi::c_void, mem::MaybeUninit, ptr::null_mut};
 use ash::vk::{self};
 use iron_oxide::graphics::{self, VkBase};
 
-use super::{vulkan_render::MAXFRAMESINFLIGHT, UniformBufferObject};
+use super::vulkan_render::MAXFRAMESINFLIGHT;
 
-pub fn create_uniform_buffers(base: &VkBase) -> ([graphics::Buffer; MAXFRAMESINFLIGHT], [*mut c_void; MAXFRAMESINFLIGHT]) {
-    let buffer_size = std::mem::size_of::<UniformBufferObject>() as u64;
+pub fn create_uniform_buffers<T>(base: &VkBase) -> ([graphics::Buffer; MAXFRAMESINFLIGHT], [*mut c_void; MAXFRAMESINFLIGHT]) {
+    let buffer_size = std::mem::size_of::<T>() as u64;
 
     #[allow(invalid_value)]
     let mut uniform_buffers: [graphics::Buffer; MAXFRAMESINFLIGHT] = [unsafe { MaybeUninit::uninit().assume_init() }; MAXFRAMESINFLIGHT];
@@ -0,0 +1,334 @@
+use std::{mem::size_of, ptr};
+
+use ash::vk::{self, AccessFlags, BufferUsageFlags, MemoryPropertyFlags, PipelineStageFlags, ShaderStageFlags};
+use iron_oxide::graphics::{Buffer, VkBase};
+
+use super::{shader_modul, MeshBatch, MeshRegistry, WorldInstance};
+use super::vulkan_render::MAXFRAMESINFLIGHT;
+use crate::game::frustum::Frustum;
+
+const WORKGROUP_SIZE: u32 = 64;
+/// Batch buffers never shrink below this many batches.
+const MIN_BATCH_CAPACITY: usize = 16;
+
+/// One draw of the world pass. The cull shader counts the visible instances into `command.instance_count`
+/// and packs them into the visible buffer starting at `batch_start`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CullBatch {
+    command: vk::DrawIndexedIndirectCommand,
+    batch_start: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct CullPushConstants {
+    planes: [[f32; 4]; 6],
+    instance_count: u32,
+    batch_count: u32,
+}
+
+/// Culls the instances of a frame against the camera frustum in a compute shader and
+/// leaves one `VkDrawIndexedIndirectCommand` per mesh batch for the world pass.
+///
+/// Every batch is drawn with its own indirect call and a vertex buffer offset instead of one multi draw,
+/// `multiDrawIndirect` and `drawIndirectFirstInstance` are optional features the device is not created with.
+pub struct InstanceCulling {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: [vk::DescriptorSet; MAXFRAMESINFLIGHT],
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+
+    /// Instances that passed the test, grouped by batch, bound as vertex binding 1 in the world pass.
+    visible_buffers: [Buffer; MAXFRAMESINFLIGHT],
+    batch_buffers: [Buffer; MAXFRAMESINFLIGHT],
+    batch_staging_buffers: [Buffer; MAXFRAMESINFLIGHT],
+    /// Instance buffer each descriptor set points at, the sets are rewritten when it was recreated.
+    bound_instance_buffers: [vk::Buffer; MAXFRAMESINFLIGHT],
+    batch_starts: [Vec<u32>; MAXFRAMESINFLIGHT],
+    push_constants: [CullPushConstants; MAXFRAMESINFLIGHT],
+}
+
+impl InstanceCulling {
+    pub fn create(base: &VkBase, instance_buffers: &[Buffer; MAXFRAMESINFLIGHT]) -> Self {
+        let device = &base.device;
+
+        let descriptor_set_layout = create_descriptor_set_layout(device);
+        let descriptor_pool = create_descriptor_pool(device);
+        let layouts = [descriptor_set_layout; MAXFRAMESINFLIGHT];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: MAXFRAMESINFLIGHT as _,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info).unwrap() };
+        let (pipeline_layout, pipeline) = create_cull_pipeline(device, descriptor_set_layout);
+
+        let mut culling = Self {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets: std::array::from_fn(|i| sets[i]),
+            pipeline_layout,
+            pipeline,
+            visible_buffers: std::array::from_fn(|i| create_visible_buffer(base, instance_buffers[i].size)),
+            batch_buffers: [(); MAXFRAMESINFLIGHT].map(|_| create_batch_buffer(base, MIN_BATCH_CAPACITY)),
+            batch_staging_buffers: [(); MAXFRAMESINFLIGHT].map(|_| create_batch_staging_buffer(base, MIN_BATCH_CAPACITY)),
+            bound_instance_buffers: [vk::Buffer::null(); MAXFRAMESINFLIGHT],
+            batch_starts: [const { Vec::new() }; MAXFRAMESINFLIGHT],
+            push_constants: [CullPushConstants::default(); MAXFRAMESINFLIGHT],
+        };
+
+        for frame in 0..MAXFRAMESINFLIGHT {
+            culling.write_descriptors(device, frame, &instance_buffers[frame]);
+        }
+
+        culling
+    }
+
+    /// Writes the batches of `frame` with an instance count of zero and grows the buffers to fit.
+    /// Must run after the frame's fence was waited on, like the instance upload.
+    pub fn prepare(&mut self, base: &VkBase, frame: usize, instance_buffer: &Buffer, instance_count: u32, batches: &[MeshBatch], meshes: &MeshRegistry, frustum: &Frustum) {
+        let device = &base.device;
+        let mut rebind = instance_buffer.inner != self.bound_instance_buffers[frame];
+
+        if self.visible_buffers[frame].size != instance_buffer.size {
+            self.visible_buffers[frame].destroy(device);
+            self.visible_buffers[frame] = create_visible_buffer(base, instance_buffer.size);
+            rebind = true;
+        }
+
+        let capacity = self.batch_staging_buffers[frame].size as usize / size_of::<CullBatch>();
+        if batches.len() > capacity {
+            let wanted = batches.len().next_power_of_two();
+            self.batch_buffers[frame].destroy(device);
+            self.batch_staging_buffers[frame].destroy(device);
+            self.batch_buffers[frame] = create_batch_buffer(base, wanted);
+            self.batch_staging_buffers[frame] = create_batch_staging_buffer(base, wanted);
+            rebind = true;
+        }
+
+        if rebind {
+            self.write_descriptors(device, frame, instance_buffer);
+        }
+
+        let cull_batches: Vec<CullBatch> = batches.iter().map(|batch| {
+            let mesh = meshes.get(batch.mesh);
+            CullBatch {
+                command: vk::DrawIndexedIndirectCommand {
+                    index_count: mesh.index_count,
+                    instance_count: 0,
+                    first_index: mesh.first_index,
+                    vertex_offset: mesh.vertex_offset,
+                    first_instance: 0,
+                },
+                batch_start: batch.first_instance,
+            }
+        }).collect();
+
+        self.batch_starts[frame] = batches.iter().map(|batch| batch.first_instance).collect();
+        self.push_constants[frame] = CullPushConstants {
+            planes: frustum.planes().map(Into::into),
+            instance_count,
+            batch_count: batches.len() as _,
+        };
+
+        if cull_batches.is_empty() {
+            return;
+        }
+
+        let staging_buffer = &self.batch_staging_buffers[frame];
+        let buffer_size = (cull_batches.len() * size_of::<CullBatch>()) as u64;
+        let mapped_memory = staging_buffer.map_memory(device, buffer_size, 0);
+        unsafe { ptr::copy_nonoverlapping(cull_batches.as_ptr(), mapped_memory as _, cull_batches.len()) };
+        staging_buffer.unmap_memory(device);
+    }
+
+    /// Resets the batches and dispatches the cull shader, outside of any render pass.
+    /// Expects the frame's instance buffer to be readable by compute shaders.
+    pub fn record(&self, device: &ash::Device, cmd_buf: vk::CommandBuffer, frame: usize) {
+        let push_constants = &self.push_constants[frame];
+        if push_constants.batch_count == 0 {
+            return;
+        }
+
+        let batch_size = push_constants.batch_count as u64 * size_of::<CullBatch>() as u64;
+        let region = vk::BufferCopy { src_offset: 0, dst_offset: 0, size: batch_size };
+
+        let reset_barrier = vk::BufferMemoryBarrier {
+            src_access_mask: AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.batch_buffers[frame].inner,
+            offset: 0,
+            size: batch_size,
+            ..Default::default()
+        };
+
+        let draw_barriers = [
+            vk::BufferMemoryBarrier {
+                src_access_mask: AccessFlags::SHADER_WRITE,
+                dst_access_mask: AccessFlags::INDIRECT_COMMAND_READ,
+                ..reset_barrier
+            },
+            vk::BufferMemoryBarrier {
+                src_access_mask: AccessFlags::SHADER_WRITE,
+                dst_access_mask: AccessFlags::VERTEX_ATTRIBUTE_READ,
+                buffer: self.visible_buffers[frame].inner,
+                size: vk::WHOLE_SIZE,
+                ..reset_barrier
+            },
+        ];
+
+        let group_count = push_constants.instance_count.div_ceil(WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_copy_buffer(cmd_buf, self.batch_staging_buffers[frame].inner, self.batch_buffers[frame].inner, &[region]);
+            device.cmd_pipeline_barrier(cmd_buf, PipelineStageFlags::TRANSFER, PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[reset_barrier], &[]);
+
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.pipeline_layout, 0, &[self.descriptor_sets[frame]], &[]);
+            let bytes = std::slice::from_raw_parts(push_constants as *const CullPushConstants as *const u8, size_of::<CullPushConstants>());
+            device.cmd_push_constants(cmd_buf, self.pipeline_layout, ShaderStageFlags::COMPUTE, 0, bytes);
+            if group_count > 0 {
+                device.cmd_dispatch(cmd_buf, group_count, 1, 1);
+            }
+
+            device.cmd_pipeline_barrier(cmd_buf, PipelineStageFlags::COMPUTE_SHADER, PipelineStageFlags::DRAW_INDIRECT | PipelineStageFlags::VERTEX_INPUT, vk::DependencyFlags::empty(), &[], &draw_barriers, &[]);
+        }
+    }
+
+    /// Draws every batch of `frame` from the culled instances. The mesh buffers must already be bound.
+    pub fn record_draws(&self, device: &ash::Device, cmd_buf: vk::CommandBuffer, frame: usize) {
+        let visible_buffer = self.visible_buffers[frame].inner;
+
+        for (i, batch_start) in self.batch_starts[frame].iter().enumerate() {
+            let instance_offset = *batch_start as u64 * size_of::<WorldInstance>() as u64;
+            unsafe {
+                device.cmd_bind_vertex_buffers(cmd_buf, 1, &[visible_buffer], &[instance_offset]);
+                device.cmd_draw_indexed_indirect(cmd_buf, self.batch_buffers[frame].inner, (i * size_of::<CullBatch>()) as _, 1, size_of::<CullBatch>() as _);
+            }
+        }
+    }
+
+    fn write_descriptors(&mut self, device: &ash::Device, frame: usize, instance_buffer: &Buffer) {
+        let buffer_infos = [instance_buffer, &self.visible_buffers[frame], &self.batch_buffers[frame]].map(|buffer| vk::DescriptorBufferInfo {
+            buffer: buffer.inner,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        });
+
+        let descriptor_writes: Vec<vk::WriteDescriptorSet> = buffer_infos.iter().enumerate().map(|(binding, buffer_info)| vk::WriteDescriptorSet {
+            dst_set: self.descriptor_sets[frame],
+            dst_binding: binding as _,
+            dst_array_element: 0,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            p_buffer_info: buffer_info,
+            ..Default::default()
+        }).collect();
+
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+        self.bound_instance_buffers[frame] = instance_buffer.inner;
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+        for frame in 0..MAXFRAMESINFLIGHT {
+            self.visible_buffers[frame].destroy(device);
+            self.batch_buffers[frame].destroy(device);
+            self.batch_staging_buffers[frame].destroy(device);
+        }
+    }
+}
+
+fn create_visible_buffer(base: &VkBase, size: u64) -> Buffer {
+    Buffer::create(base, size, BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::VERTEX_BUFFER, MemoryPropertyFlags::DEVICE_LOCAL)
+}
+
+fn create_batch_buffer(base: &VkBase, capacity: usize) -> Buffer {
+    Buffer::create(base, (capacity * size_of::<CullBatch>()) as u64, BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::INDIRECT_BUFFER | BufferUsageFlags::TRANSFER_DST, MemoryPropertyFlags::DEVICE_LOCAL)
+}
+
+fn create_batch_staging_buffer(base: &VkBase, capacity: usize) -> Buffer {
+    Buffer::create(base, (capacity * size_of::<CullBatch>()) as u64, BufferUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT)
+}
+
+fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    // Instances, visible instances, batches
+    let bindings: [vk::DescriptorSetLayoutBinding; 3] = std::array::from_fn(|binding| vk::DescriptorSetLayoutBinding {
+        binding: binding as _,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+        stage_flags: ShaderStageFlags::COMPUTE,
+        ..Default::default()
+    });
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo {
+        binding_count: bindings.len() as _,
+        p_bindings: bindings.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+}
+
+fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: MAXFRAMESINFLIGHT as u32 * 3,
+    };
+
+    let pool_info = vk::DescriptorPoolCreateInfo {
+        pool_size_count: 1,
+        p_pool_sizes: &pool_size,
+        max_sets: MAXFRAMESINFLIGHT as _,
+        ..Default::default()
+    };
+
+    unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() }
+}
+
+fn create_cull_pipeline(device: &ash::Device, descriptor_set_layout: vk::DescriptorSetLayout) -> (vk::PipelineLayout, vk::Pipeline) {
+    let shader_module = shader_modul::create_shader_modul(device, include_bytes!("../../spv/cull.comp.spv"));
+
+    let push_constant_range = vk::PushConstantRange {
+        stage_flags: ShaderStageFlags::COMPUTE,
+        offset: 0,
+        size: size_of::<CullPushConstants>() as _,
+    };
+
+    let layout_info = vk::PipelineLayoutCreateInfo {
+        set_layout_count: 1,
+        p_set_layouts: &descriptor_set_layout,
+        push_constant_range_count: 1,
+        p_push_constant_ranges: &push_constant_range,
+        ..Default::default()
+    };
+    let pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None).unwrap() };
+
+    let create_info = vk::ComputePipelineCreateInfo {
+        stage: vk::PipelineShaderStageCreateInfo {
+            stage: ShaderStageFlags::COMPUTE,
+            module: shader_module,
+            p_name: c"main".as_ptr(),
+            ..Default::default()
+        },
+        layout: pipeline_layout,
+        base_pipeline_index: -1,
+        ..Default::default()
+    };
+
+    let pipeline = unsafe { device.create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None).unwrap()[0] };
+
+    unsafe { device.destroy_shader_module(shader_module, None) };
+
+    (pipeline_layout, pipeline)
+}
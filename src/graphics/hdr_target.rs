@@ -0,0 +1,59 @@
+use ash::vk::{self, Extent3D, Format, ImageUsageFlags, MemoryPropertyFlags};
+use iron_oxide::graphics::{self, VkBase};
+use winit::dpi::PhysicalSize;
+
+use super::Attachment;
+
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Floating point color and depth the world pass renders into, sampled by the post pipeline afterwards.
+/// With MSAA the world is drawn into `msaa_color` and resolved into `color_image` at the end of the pass.
+pub struct HdrTarget {
+    pub color_image: graphics::Image,
+    pub msaa_color: Option<Attachment>,
+    pub depth_image: Attachment,
+    pub framebuffer: vk::Framebuffer,
+    pub size: PhysicalSize<u32>,
+}
+
+impl HdrTarget {
+    /// The sample count is taken from `depth_image`.
+    pub fn create(base: &VkBase, render_pass: vk::RenderPass, depth_image: Attachment, size: PhysicalSize<u32>) -> Self {
+        let extent = Extent3D { width: size.width, height: size.height, depth: 1 };
+        let mut color_image = graphics::Image::create(base, extent, HDR_FORMAT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::SAMPLED, MemoryPropertyFlags::DEVICE_LOCAL);
+        color_image.create_view(base, vk::ImageAspectFlags::COLOR);
+
+        let msaa_color = (depth_image.samples != vk::SampleCountFlags::TYPE_1).then(|| {
+            let extent = vk::Extent2D { width: size.width, height: size.height };
+            Attachment::create(base, extent, HDR_FORMAT, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSIENT_ATTACHMENT, depth_image.samples, vk::ImageAspectFlags::COLOR)
+        });
+
+        // Attachment order matches `VulkanRender::create_world_render_pass`
+        let attachments = match &msaa_color {
+            Some(msaa_color) => vec![msaa_color.view, depth_image.view, color_image.view],
+            None => vec![color_image.view, depth_image.view],
+        };
+        let create_info = vk::FramebufferCreateInfo {
+            render_pass,
+            attachment_count: attachments.len() as _,
+            p_attachments: attachments.as_ptr(),
+            width: size.width,
+            height: size.height,
+            layers: 1,
+            ..Default::default()
+        };
+
+        let framebuffer = unsafe { base.device.create_framebuffer(&create_info, None).unwrap() };
+
+        Self { color_image, msaa_color, depth_image, framebuffer, size }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_framebuffer(self.framebuffer, None) };
+        self.color_image.destroy(device);
+        if let Some(msaa_color) = &self.msaa_color {
+            msaa_color.destroy(device);
+        }
+        self.depth_image.destroy(device);
+    }
+}
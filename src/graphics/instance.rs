@@ -1,7 +1,23 @@
 use std::mem::offset_of;
 
 use ash::vk;
-use cgmath::Vector2;
+use cgmath::{Matrix4, Vector2};
+
+pub const INSTANCE_SELECTED: u32 = 1;
+pub const INSTANCE_HOVERED: u32 = 1 << 1;
+
+/// Per-instance data of the world pipeline, bound as vertex binding 1.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WorldInstance {
+    pub model: Matrix4<f32>,
+    pub base_color: [f32; 4],
+    pub flags: u32,
+    pub texture_layer: i32,
+    pub roughness: f32,
+    /// `SamplerPreset::index` of the material.
+    pub sampler: u32,
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
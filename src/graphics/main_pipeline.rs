@@ -2,7 +2,7 @@
 use ash::vk::{self};
 use super::{shader_modul, Vertex};
 
-pub fn create_main_pipeline(device: &ash::Device, window_size: winit::dpi::PhysicalSize<u32>, render_pass: vk::RenderPass, descriptor_set_layout: vk::DescriptorSetLayout) -> (vk::PipelineLayout, vk::Pipeline) {
+pub fn create_main_pipeline(device: &ash::Device, window_size: winit::dpi::PhysicalSize<u32>, render_pass: vk::RenderPass, descriptor_set_layout: vk::DescriptorSetLayout, samples: vk::SampleCountFlags) -> (vk::PipelineLayout, vk::Pipeline) {
     let vertex_shader_buff= include_bytes!("../../spv/shader.vert.spv");
     let fragment_shader_buff = include_bytes!("../../spv/shader.frag.spv");
 
@@ -84,7 +84,7 @@ pub fn create_main_pipeline(device: &ash::Device, window_size: winit::dpi::Physi
 
     let multisampling = vk::PipelineMultisampleStateCreateInfo {
         sample_shading_enable: vk::FALSE,
-        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        rasterization_samples: samples,
         min_sample_shading: 1.0,
         ..Default::default()
     };
@@ -0,0 +1,37 @@
+use super::SamplerPreset;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    /// Layer of the `TextureRegistry` multiplied onto `base_color`, `None` for a plain color.
+    pub texture_layer: Option<u32>,
+    pub roughness: f32,
+    pub sampler: SamplerPreset,
+}
+
+impl Material {
+    pub const DEFAULT: Self = Self::color(0.8, 0.8, 0.8);
+    pub const CARDBOARD: Self = Self::color(0.62, 0.45, 0.28);
+    pub const WOOD: Self = Self { roughness: 0.6, ..Self::color(0.55, 0.38, 0.22) };
+    pub const PLASTIC: Self = Self { roughness: 0.35, ..Self::color(0.2, 0.35, 0.7) };
+    pub const CONCRETE: Self = Self { roughness: 0.95, ..Self::color(0.5, 0.5, 0.48) };
+
+    pub const fn color(r: f32, g: f32, b: f32) -> Self {
+        Self { base_color: [r, g, b, 1.0], texture_layer: None, roughness: 0.8, sampler: SamplerPreset::DEFAULT }
+    }
+
+    pub const fn textured(layer: u32) -> Self {
+        Self { base_color: [1.0; 4], texture_layer: Some(layer), roughness: 0.8, sampler: SamplerPreset::DEFAULT }
+    }
+
+    /// Texture layer as stored in `WorldInstance`, -1 means untextured.
+    pub fn shader_texture_layer(&self) -> i32 {
+        self.texture_layer.map_or(-1, |layer| layer as i32)
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,231 @@
+use std::f32::consts::TAU;
+use cgmath::{Vector2, Vector3};
+
+use super::Vertex;
+
+/// Index into the `MeshRegistry`. The built in meshes always occupy the first ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MeshId(pub u32);
+
+impl MeshId {
+    pub const CUBE: Self = Self(0);
+    pub const CYLINDER: Self = Self(1);
+    pub const SHELF: Self = Self(2);
+    pub const OPEN_BIN: Self = Self(3);
+    pub const PLANE: Self = Self(4);
+    /// Ids from here on belong to meshes added at runtime, which only live as long as the session.
+    pub const BUILTIN_COUNT: u32 = 5;
+
+    pub const fn is_builtin(self) -> bool {
+        self.0 < Self::BUILTIN_COUNT
+    }
+}
+
+/// Where a mesh lives inside the shared vertex and index buffers.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshRange {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Consecutive instances that use the same mesh and sampler, drawn with a single `cmd_draw_indexed`.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshBatch {
+    pub mesh: MeshId,
+    pub first_instance: u32,
+    pub instance_count: u32,
+}
+
+/// All meshes packed into one vertex and one index list, so the renderer binds a single pair of buffers.
+/// Every mesh fits the unit cube around the origin, the instance matrix scales it to the object size.
+pub struct MeshRegistry {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    meshes: Vec<MeshRange>,
+}
+
+impl MeshRegistry {
+    /// Registry with the built in meshes, in the order of the `MeshId` constants.
+    pub fn new() -> Self {
+        let mut registry = Self { vertices: Vec::new(), indices: Vec::new(), meshes: Vec::new() };
+
+        registry.add(cube());
+        registry.add(cylinder(24));
+        registry.add(shelf());
+        registry.add(open_bin());
+        registry.add(plane());
+        debug_assert_eq!(registry.mesh_count(), MeshId::BUILTIN_COUNT as usize);
+
+        registry
+    }
+
+    /// Indices are relative to the mesh's own vertices.
+    pub fn add(&mut self, (vertices, indices): (Vec<Vertex>, Vec<u32>)) -> MeshId {
+        let range = MeshRange {
+            first_index: self.indices.len() as _,
+            index_count: indices.len() as _,
+            vertex_offset: self.vertices.len() as _,
+        };
+
+        self.vertices.extend(vertices);
+        self.indices.extend(indices);
+        self.meshes.push(range);
+
+        MeshId(self.meshes.len() as u32 - 1)
+    }
+
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Unknown ids, e.g. from a world saved with a mesh this build does not have, fall back to the cube.
+    pub fn get(&self, id: MeshId) -> MeshRange {
+        self.meshes.get(id.0 as usize).copied().unwrap_or(self.meshes[MeshId::CUBE.0 as usize])
+    }
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, pos: Vector3<f32>, nrm: Vector3<f32>, uv: Vector2<f32>) -> u32 {
+        self.vertices.push(Vertex { pos, nrm, uv, padding: 0.0 });
+        self.vertices.len() as u32 - 1
+    }
+
+    /// Corners in order around the quad.
+    fn quad(&mut self, corners: [Vector3<f32>; 4], nrm: Vector3<f32>) {
+        let uvs = [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(1.0, 1.0), Vector2::new(0.0, 1.0)];
+        let first = self.vertices.len() as u32;
+        for (corner, uv) in corners.into_iter().zip(uvs) {
+            self.vertex(corner, nrm, uv);
+        }
+        self.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+    }
+
+    /// Axis aligned box between `min` and `max`, faces listed in `skip` are left out.
+    fn cuboid(&mut self, min: Vector3<f32>, max: Vector3<f32>, skip: &[Face]) {
+        let corner = |x: bool, y: bool, z: bool| Vector3::new(
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        );
+
+        let faces = [
+            (Face::Front, [corner(false, false, false), corner(true, false, false), corner(true, true, false), corner(false, true, false)], -Vector3::unit_z()),
+            (Face::Back, [corner(false, false, true), corner(true, false, true), corner(true, true, true), corner(false, true, true)], Vector3::unit_z()),
+            (Face::Left, [corner(false, false, false), corner(false, true, false), corner(false, true, true), corner(false, false, true)], -Vector3::unit_x()),
+            (Face::Right, [corner(true, false, false), corner(true, true, false), corner(true, true, true), corner(true, false, true)], Vector3::unit_x()),
+            (Face::Top, [corner(false, true, false), corner(true, true, false), corner(true, true, true), corner(false, true, true)], Vector3::unit_y()),
+            (Face::Bottom, [corner(false, false, false), corner(true, false, false), corner(true, false, true), corner(false, false, true)], -Vector3::unit_y()),
+        ];
+
+        for (face, corners, nrm) in faces {
+            if !skip.contains(&face) {
+                self.quad(corners, nrm);
+            }
+        }
+    }
+
+    fn finish(self) -> (Vec<Vertex>, Vec<u32>) {
+        (self.vertices, self.indices)
+    }
+}
+
+#[derive(PartialEq)]
+enum Face {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// Unit cube, the uv of every face is its x/y position so textures line up with the old cube mesh.
+pub fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    let mut builder = MeshBuilder::default();
+    builder.cuboid(Vector3::new(-0.5, -0.5, -0.5), Vector3::new(0.5, 0.5, 0.5), &[]);
+    for vertex in &mut builder.vertices {
+        vertex.uv = Vector2::new(vertex.pos.x + 0.5, vertex.pos.y + 0.5);
+    }
+    builder.finish()
+}
+
+/// Upright cylinder along y with `segments` sides and flat caps.
+pub fn cylinder(segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut builder = MeshBuilder::default();
+
+    for i in 0..segments {
+        let (a, b) = (i as f32 / segments as f32, (i + 1) as f32 / segments as f32);
+        let (sin_a, cos_a) = (a * TAU).sin_cos();
+        let (sin_b, cos_b) = (b * TAU).sin_cos();
+
+        let first = builder.vertex(Vector3::new(cos_a * 0.5, -0.5, sin_a * 0.5), Vector3::new(cos_a, 0.0, sin_a), Vector2::new(a, 0.0));
+        builder.vertex(Vector3::new(cos_b * 0.5, -0.5, sin_b * 0.5), Vector3::new(cos_b, 0.0, sin_b), Vector2::new(b, 0.0));
+        builder.vertex(Vector3::new(cos_b * 0.5, 0.5, sin_b * 0.5), Vector3::new(cos_b, 0.0, sin_b), Vector2::new(b, 1.0));
+        builder.vertex(Vector3::new(cos_a * 0.5, 0.5, sin_a * 0.5), Vector3::new(cos_a, 0.0, sin_a), Vector2::new(a, 1.0));
+        builder.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+    }
+
+    for (y, nrm) in [(0.5, Vector3::unit_y()), (-0.5, -Vector3::unit_y())] {
+        let center = builder.vertex(Vector3::new(0.0, y, 0.0), nrm, Vector2::new(0.5, 0.5));
+        for i in 0..=segments {
+            let (sin, cos) = (i as f32 / segments as f32 * TAU).sin_cos();
+            builder.vertex(Vector3::new(cos * 0.5, y, sin * 0.5), nrm, Vector2::new(cos * 0.5 + 0.5, sin * 0.5 + 0.5));
+        }
+        for i in 0..segments {
+            builder.indices.extend_from_slice(&[center, center + 1 + i, center + 2 + i]);
+        }
+    }
+
+    builder.finish()
+}
+
+/// Four corner posts with a bottom, middle and top board.
+pub fn shelf() -> (Vec<Vertex>, Vec<u32>) {
+    const POST: f32 = 0.05;
+    const BOARD: f32 = 0.04;
+
+    let mut builder = MeshBuilder::default();
+
+    for (x, z) in [(-0.5, -0.5), (0.5 - POST, -0.5), (-0.5, 0.5 - POST), (0.5 - POST, 0.5 - POST)] {
+        builder.cuboid(Vector3::new(x, -0.5, z), Vector3::new(x + POST, 0.5, z + POST), &[]);
+    }
+    for y in [-0.5 + POST, -BOARD / 2.0, 0.5 - BOARD] {
+        builder.cuboid(Vector3::new(-0.5, y, -0.5), Vector3::new(0.5, y + BOARD, 0.5), &[]);
+    }
+
+    builder.finish()
+}
+
+/// Box with thin walls and no lid, open towards +y.
+pub fn open_bin() -> (Vec<Vertex>, Vec<u32>) {
+    const WALL: f32 = 0.04;
+
+    let mut builder = MeshBuilder::default();
+
+    builder.cuboid(Vector3::new(-0.5, -0.5, -0.5), Vector3::new(0.5, -0.5 + WALL, 0.5), &[]);
+    builder.cuboid(Vector3::new(-0.5, -0.5 + WALL, -0.5), Vector3::new(0.5, 0.5, -0.5 + WALL), &[Face::Bottom]);
+    builder.cuboid(Vector3::new(-0.5, -0.5 + WALL, 0.5 - WALL), Vector3::new(0.5, 0.5, 0.5), &[Face::Bottom]);
+    builder.cuboid(Vector3::new(-0.5, -0.5 + WALL, -0.5 + WALL), Vector3::new(-0.5 + WALL, 0.5, 0.5 - WALL), &[Face::Bottom, Face::Front, Face::Back]);
+    builder.cuboid(Vector3::new(0.5 - WALL, -0.5 + WALL, -0.5 + WALL), Vector3::new(0.5, 0.5, 0.5 - WALL), &[Face::Bottom, Face::Front, Face::Back]);
+
+    builder.finish()
+}
+
+/// Flat quad in the xz plane facing +y.
+pub fn plane() -> (Vec<Vertex>, Vec<u32>) {
+    let mut builder = MeshBuilder::default();
+    builder.quad([
+        Vector3::new(-0.5, 0.0, -0.5),
+        Vector3::new(0.5, 0.0, -0.5),
+        Vector3::new(0.5, 0.0, 0.5),
+        Vector3::new(-0.5, 0.0, 0.5),
+    ], Vector3::unit_y());
+    builder.finish()
+}
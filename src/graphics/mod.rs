@@ -7,8 +7,34 @@ mod instance;
 mod main_pipeline;
 mod post_pipeline;
 mod shader_modul;
+mod offscreen;
+mod material;
+mod screenshot;
+mod shadow_map;
+mod shadow_pipeline;
+mod hdr_target;
+mod attachment;
+mod post_process;
+mod mesh;
+mod model;
+mod texture;
+mod sampler;
+mod culling;
 
-pub use vulkan_render::VulkanRender;
+pub use vulkan_render::{VulkanRender, FONT_SHADERS, UI_SHADERS};
 pub use swapchain::Swapchain;
-pub use uniform_buffer_object::UniformBufferObject;
+pub use uniform_buffer_object::{PointLightData, UiUniformBufferObject, UniformBufferObject, MAX_POINT_LIGHTS};
 pub use vertex::Vertex;
+pub use offscreen::OffscreenTarget;
+pub use material::Material;
+pub use instance::{WorldInstance, INSTANCE_HOVERED, INSTANCE_SELECTED};
+pub use screenshot::{save_screenshot, write_png, ScreenshotMode};
+pub use shadow_map::{ShadowMap, ShadowSettings, DEPTH_CORRECTION};
+pub use hdr_target::HdrTarget;
+pub use post_process::{PostEffect, PostProcess};
+pub use attachment::Attachment;
+pub use mesh::{MeshBatch, MeshId, MeshRange, MeshRegistry};
+pub use model::{Model, ModelError, ModelMaterial, ModelMesh, TextureSource};
+pub use texture::{TextureArray, TextureRegistry, BUILTIN_TEXTURE_LAYERS, MAX_TEXTURE_LAYERS};
+pub use sampler::{SamplerPreset, TextureFilter, TextureWrap};
+pub use culling::InstanceCulling;
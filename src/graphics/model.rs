@@ -0,0 +1,368 @@
+use std::{fmt, path::{Path, PathBuf}};
+use cgmath::{ElementWise, InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector2, Vector3, Zero};
+
+use super::{Material, SamplerPreset, TextureFilter, TextureWrap, Vertex};
+
+#[derive(Debug)]
+pub enum ModelError {
+    UnsupportedFormat(PathBuf),
+    Gltf(gltf::Error),
+    Obj(tobj::LoadError),
+    MissingPositions(PathBuf),
+    Empty(PathBuf),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(path) => write!(f, "{} is neither a glTF nor an OBJ file", path.display()),
+            Self::Gltf(err) => write!(f, "invalid glTF file: {err}"),
+            Self::Obj(err) => write!(f, "invalid OBJ file: {err}"),
+            Self::MissingPositions(path) => write!(f, "{} has a primitive without vertex positions", path.display()),
+            Self::Empty(path) => write!(f, "{} contains no triangles", path.display()),
+        }
+    }
+}
+
+/// Image a material refers to. glTF binaries may embed their images instead of pointing to a file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureSource {
+    File(PathBuf),
+    Embedded(Vec<u8>),
+}
+
+impl fmt::Display for TextureSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Embedded(bytes) => write!(f, "embedded image ({} bytes)", bytes.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelMaterial {
+    pub material: Material,
+    pub texture: Option<TextureSource>,
+}
+
+impl Default for ModelMaterial {
+    fn default() -> Self {
+        Self { material: Material::DEFAULT, texture: None }
+    }
+}
+
+/// Triangles of one material, indices are relative to its own vertices.
+#[derive(Debug, Clone)]
+pub struct ModelMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<ModelMaterial>,
+}
+
+impl Model {
+    /// Loads a `.gltf`, `.glb` or `.obj` file, picked by extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ModelError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+
+        let model = match extension.as_deref() {
+            Some("gltf" | "glb") => load_gltf(path)?,
+            Some("obj") => load_obj(path)?,
+            _ => return Err(ModelError::UnsupportedFormat(path.to_path_buf())),
+        };
+
+        if model.meshes.iter().all(|mesh| mesh.indices.is_empty()) {
+            return Err(ModelError::Empty(path.to_path_buf()));
+        }
+
+        Ok(model)
+    }
+
+    pub fn material(&self, mesh: &ModelMesh) -> ModelMaterial {
+        mesh.material.and_then(|index| self.materials.get(index)).cloned().unwrap_or_default()
+    }
+
+    /// Centers the model on the origin and scales it into the unit cube the `MeshRegistry` expects.
+    /// Returns the original size, which becomes the object size so the model keeps its real dimensions.
+    pub fn fit_unit_cube(&mut self) -> Vector3<f32> {
+        let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in self.meshes.iter().flat_map(|mesh| &mesh.vertices) {
+            min = Vector3::new(min.x.min(vertex.pos.x), min.y.min(vertex.pos.y), min.z.min(vertex.pos.z));
+            max = Vector3::new(max.x.max(vertex.pos.x), max.y.max(vertex.pos.y), max.z.max(vertex.pos.z));
+        }
+
+        let size = max - min;
+        let center = (min + max) / 2.0;
+        // Flat models keep a zero size on that axis instead of dividing by it
+        let scale = size.map(|extent| if extent > 0.0 { 1.0 / extent } else { 1.0 });
+
+        for vertex in self.meshes.iter_mut().flat_map(|mesh| &mut mesh.vertices) {
+            vertex.pos = (vertex.pos - center).mul_element_wise(scale);
+            // Inverse transpose of the scale, the instance matrix undoes it again
+            let normal = vertex.nrm.div_element_wise(scale);
+            vertex.nrm = if normal.is_zero() { normal } else { normal.normalize() };
+        }
+
+        size
+    }
+}
+
+fn load_gltf(path: &Path) -> Result<Model, ModelError> {
+    let gltf = gltf::Gltf::open(path).map_err(ModelError::Gltf)?;
+    let base = path.parent();
+    let buffers = gltf::import_buffers(&gltf.document, base, gltf.blob.clone()).map_err(ModelError::Gltf)?;
+
+    let materials = gltf.document.materials().map(|material| {
+        let pbr = material.pbr_metallic_roughness();
+        let texture = pbr.base_color_texture().and_then(|info| match info.texture().source().source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &buffers[view.buffer().index()];
+                Some(TextureSource::Embedded(buffer[view.offset()..view.offset() + view.length()].to_vec()))
+            },
+            gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                log::warn!("{}: data uri textures are not supported", path.display());
+                None
+            },
+            gltf::image::Source::Uri { uri, .. } => Some(TextureSource::File(base.unwrap_or(Path::new("")).join(uri))),
+        });
+
+        let sampler = pbr.base_color_texture().map_or(SamplerPreset::DEFAULT, |info| gltf_sampler(info.texture().sampler()));
+
+        ModelMaterial {
+            material: Material { base_color: pbr.base_color_factor(), texture_layer: None, roughness: pbr.roughness_factor(), sampler },
+            texture,
+        }
+    }).collect();
+
+    let mut model = Model { meshes: Vec::new(), materials };
+
+    let Some(scene) = gltf.document.default_scene().or_else(|| gltf.document.scenes().next()) else {
+        return Ok(model);
+    };
+
+    let mut nodes: Vec<(gltf::Node, Matrix4<f32>)> = scene.nodes().map(|node| (node, Matrix4::identity())).collect();
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = parent * Matrix4::from(node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform)));
+
+        let Some(mesh) = node.mesh() else { continue };
+        let normal_matrix = normal_matrix(transform);
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("{}: skipping {:?} primitive of mesh {}", path.display(), primitive.mode(), mesh.index());
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vector3<f32>> = reader.read_positions()
+                .ok_or_else(|| ModelError::MissingPositions(path.to_path_buf()))?
+                .map(|position| (transform * Vector3::from(position).extend(1.0)).truncate())
+                .collect();
+            let normals: Option<Vec<Vector3<f32>>> = reader.read_normals().map(|normals| normals.map(|normal| (normal_matrix * Vector3::from(normal)).normalize()).collect());
+            let uvs: Option<Vec<Vector2<f32>>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().map(Vector2::from).collect());
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            model.meshes.push(build_mesh(positions, normals, uvs, indices, primitive.material().index()));
+        }
+    }
+
+    Ok(model)
+}
+
+fn gltf_sampler(sampler: gltf::texture::Sampler) -> SamplerPreset {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let filter = match (sampler.mag_filter(), sampler.min_filter()) {
+        (Some(MagFilter::Nearest), _) => TextureFilter::Nearest,
+        (_, Some(MinFilter::Linear | MinFilter::Nearest)) => TextureFilter::Linear,
+        _ => TextureFilter::Trilinear,
+    };
+    let wrap = match sampler.wrap_s() {
+        WrappingMode::ClampToEdge => TextureWrap::Clamp,
+        WrappingMode::Repeat | WrappingMode::MirroredRepeat => TextureWrap::Repeat,
+    };
+
+    SamplerPreset { filter, wrap }
+}
+
+fn load_obj(path: &Path) -> Result<Model, ModelError> {
+    let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(ModelError::Obj)?;
+
+    // A missing .mtl should not stop the geometry from loading
+    let materials = materials.unwrap_or_else(|err| {
+        log::warn!("{}: no materials loaded: {err}", path.display());
+        Vec::new()
+    });
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let materials = materials.into_iter().map(|material| {
+        let [r, g, b] = material.diffuse.unwrap_or([0.8; 3]);
+        // Blinn-Phong exponent to roughness, Ns 0 is fully rough and Ns 1000 almost a mirror
+        let roughness = material.shininess.map_or(0.8, |shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt());
+
+        ModelMaterial {
+            material: Material { base_color: [r, g, b, material.dissolve.unwrap_or(1.0)], texture_layer: None, roughness, sampler: SamplerPreset::DEFAULT },
+            texture: material.diffuse_texture.map(|texture| TextureSource::File(base.join(texture))),
+        }
+    }).collect();
+
+    let meshes = models.into_iter().map(|model| {
+        let mesh = model.mesh;
+        let positions = mesh.positions.chunks_exact(3).map(|p| Vector3::new(p[0], p[1], p[2])).collect();
+        let normals = (!mesh.normals.is_empty()).then(|| mesh.normals.chunks_exact(3).map(|n| Vector3::new(n[0], n[1], n[2])).collect());
+        // OBJ puts v = 0 at the bottom of the image, Vulkan samples it from the top
+        let uvs = (!mesh.texcoords.is_empty()).then(|| mesh.texcoords.chunks_exact(2).map(|uv| Vector2::new(uv[0], 1.0 - uv[1])).collect());
+
+        build_mesh(positions, normals, uvs, mesh.indices, mesh.material_id)
+    }).collect();
+
+    Ok(Model { meshes, materials })
+}
+
+fn normal_matrix(transform: Matrix4<f32>) -> Matrix3<f32> {
+    let linear = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+    linear.invert().map_or(linear, |inverse| inverse.transpose())
+}
+
+/// Files without normals get smooth ones averaged from the surrounding triangles.
+fn build_mesh(positions: Vec<Vector3<f32>>, normals: Option<Vec<Vector3<f32>>>, uvs: Option<Vec<Vector2<f32>>>, indices: Vec<u32>, material: Option<usize>) -> ModelMesh {
+    // Broken files can reference vertices that do not exist, those triangles are dropped
+    let indices: Vec<u32> = indices.chunks_exact(3)
+        .filter(|triangle| triangle.iter().all(|&i| (i as usize) < positions.len()))
+        .flatten()
+        .copied()
+        .collect();
+
+    let normals = normals.unwrap_or_else(|| {
+        let mut normals = vec![Vector3::zero(); positions.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+            for i in [a, b, c] {
+                normals[i] += normal;
+            }
+        }
+        normals.into_iter().map(|normal| if normal.is_zero() { normal } else { normal.normalize() }).collect()
+    });
+
+    let vertices = positions.iter().enumerate().map(|(i, &pos)| Vertex {
+        pos,
+        nrm: normals.get(i).copied().unwrap_or(Vector3::zero()),
+        uv: uvs.as_ref().and_then(|uvs| uvs.get(i).copied()).unwrap_or(Vector2::zero()),
+        padding: 0.0,
+    }).collect();
+
+    ModelMesh { vertices, indices, material }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use cgmath::{Vector2, Vector3};
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/models").join(name)
+    }
+
+    fn assert_close(actual: Vector3<f32>, expected: [f32; 3]) {
+        let expected = Vector3::from(expected);
+        assert!((actual - expected).magnitude() < 1e-5, "{actual:?} != {expected:?}");
+    }
+
+    fn vertex_at(mesh: &ModelMesh, position: [f32; 3]) -> &Vertex {
+        mesh.vertices.iter().find(|vertex| (vertex.pos - Vector3::from(position)).magnitude() < 1e-5)
+            .unwrap_or_else(|| panic!("no vertex at {position:?}"))
+    }
+
+    #[test]
+    fn obj_quad_loads_with_generated_normals() {
+        let model = Model::load(fixture("quad.obj")).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        for vertex in &mesh.vertices {
+            assert_close(vertex.nrm, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn obj_flips_v() {
+        let model = Model::load(fixture("quad.obj")).unwrap();
+        let mesh = &model.meshes[0];
+
+        assert_eq!(vertex_at(mesh, [0.0, 0.0, 0.0]).uv, Vector2::new(0.0, 1.0));
+        assert_eq!(vertex_at(mesh, [1.0, 0.0, 1.0]).uv, Vector2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn obj_imports_mtl_material() {
+        let model = Model::load(fixture("quad.obj")).unwrap();
+        let ModelMaterial { material, texture } = model.material(&model.meshes[0]);
+
+        assert_eq!(material.base_color, [0.2, 0.4, 0.6, 0.5]);
+        assert!((material.roughness - (2.0f32 / 100.0).sqrt()).abs() < 1e-6);
+        assert_eq!(material.texture_layer, None);
+        assert_eq!(texture, Some(TextureSource::File(fixture("paint.png"))));
+    }
+
+    #[test]
+    fn gltf_applies_node_transforms() {
+        let model = Model::load(fixture("triangle.gltf")).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+
+        // The child node scales by 2, its parent moves it up by 2
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_close(mesh.vertices[0].pos, [0.0, 2.0, 0.0]);
+        assert_close(mesh.vertices[1].pos, [2.0, 2.0, 0.0]);
+        assert_close(mesh.vertices[2].pos, [0.0, 4.0, 0.0]);
+        for vertex in &mesh.vertices {
+            assert_close(vertex.nrm, [0.0, 0.0, 1.0]);
+        }
+
+        let ModelMaterial { material, texture } = model.material(mesh);
+        assert_eq!(material.base_color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(material.roughness, 0.3);
+        assert_eq!(texture, None);
+    }
+
+    #[test]
+    fn glb_without_normals_or_indices() {
+        let model = Model::load(fixture("triangle.glb")).unwrap();
+        let mesh = &model.meshes[0];
+
+        // Rotated a quarter turn around x the triangle lies flat and faces down
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_close(mesh.vertices[1].pos, [1.0, 0.0, 0.0]);
+        assert_close(mesh.vertices[2].pos, [0.0, 0.0, 1.0]);
+        for vertex in &mesh.vertices {
+            assert_close(vertex.nrm, [0.0, -1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn fit_unit_cube_keeps_flat_axes() {
+        let mut model = Model::load(fixture("triangle.gltf")).unwrap();
+        let size = model.fit_unit_cube();
+
+        assert_close(size, [2.0, 2.0, 0.0]);
+        assert_close(model.meshes[0].vertices[0].pos, [-0.5, -0.5, 0.0]);
+        assert_close(model.meshes[0].vertices[2].pos, [-0.5, 0.5, 0.0]);
+    }
+}
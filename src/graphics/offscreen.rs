@@ -0,0 +1,81 @@
+use ash::vk::{self, Extent3D, Format, ImageUsageFlags, MemoryPropertyFlags};
+use iron_oxide::graphics::{self, Buffer, SinlgeTimeCommands, VkBase};
+use winit::dpi::PhysicalSize;
+
+pub const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+pub struct OffscreenTarget {
+    pub color_image: graphics::Image,
+    pub framebuffer: vk::Framebuffer,
+    pub readback_buffer: Buffer,
+    pub size: PhysicalSize<u32>,
+}
+
+impl OffscreenTarget {
+    pub fn create(base: &VkBase, size: PhysicalSize<u32>) -> Self {
+        let extent = Extent3D { width: size.width, height: size.height, depth: 1 };
+        let mut color_image = graphics::Image::create(base, extent, OFFSCREEN_FORMAT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::DEVICE_LOCAL);
+        color_image.create_view(base, vk::ImageAspectFlags::COLOR);
+
+        let readback_size = size.width as u64 * size.height as u64 * 4;
+        let readback_buffer = Buffer::create(base, readback_size, vk::BufferUsageFlags::TRANSFER_DST, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT);
+
+        Self {
+            color_image,
+            framebuffer: vk::Framebuffer::null(),
+            readback_buffer,
+            size,
+        }
+    }
+
+    pub fn create_framebuffer(&mut self, base: &VkBase, render_pass: vk::RenderPass, attachment: vk::ImageView) {
+        let attachments = [self.color_image.view, attachment];
+        let create_info = vk::FramebufferCreateInfo {
+            render_pass,
+            attachment_count: attachments.len() as _,
+            p_attachments: attachments.as_ptr(),
+            width: self.size.width,
+            height: self.size.height,
+            layers: 1,
+            ..Default::default()
+        };
+
+        self.framebuffer = unsafe { base.device.create_framebuffer(&create_info, None).unwrap() };
+    }
+
+    /// Copies the color attachment into host memory as tightly packed RGBA8 rows.
+    /// The render pass leaves the image in `TRANSFER_SRC_OPTIMAL`, so this must only run after the frame finished.
+    pub fn read_pixels(&self, base: &VkBase, command_pool: vk::CommandPool) -> Vec<u8> {
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: Extent3D { width: self.size.width, height: self.size.height, depth: 1 },
+        };
+
+        let cmd_buf = SinlgeTimeCommands::begin(base, command_pool);
+        unsafe { base.device.cmd_copy_image_to_buffer(cmd_buf, self.color_image.inner, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.readback_buffer.inner, &[region]) };
+        SinlgeTimeCommands::end(base, command_pool, cmd_buf);
+
+        let size = self.readback_buffer.size;
+        let mut pixels = vec![0u8; size as usize];
+        let mapped_memory = self.readback_buffer.map_memory(&base.device, size, 0);
+        unsafe { std::ptr::copy_nonoverlapping(mapped_memory as *const u8, pixels.as_mut_ptr(), size as usize) };
+        self.readback_buffer.unmap_memory(&base.device);
+
+        pixels
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_framebuffer(self.framebuffer, None) };
+        self.color_image.destroy(device);
+        self.readback_buffer.destroy(device);
+    }
+}
@@ -2,10 +2,9 @@
 use ash::vk::{self};
 use super::shader_modul;
 
-#[allow(unused)]
-pub fn create_post_pipeline(device: &ash::Device, window_size: winit::dpi::PhysicalSize<u32>, render_pass: vk::RenderPass, descriptor_set_layout: &vk::DescriptorSetLayout) -> (vk::PipelineLayout, vk::Pipeline) {
+/// Fullscreen triangle pipeline for `fragment_shader_buff`, which reads its input from the descriptor set.
+pub fn create_post_pipeline(device: &ash::Device, window_size: winit::dpi::PhysicalSize<u32>, render_pass: vk::RenderPass, descriptor_set_layout: &vk::DescriptorSetLayout, fragment_shader_buff: &[u8]) -> (vk::PipelineLayout, vk::Pipeline) {
     let vertex_shader_buff= include_bytes!("../../spv/passthrough.vert.spv");
-    let fragment_shader_buff = include_bytes!("../../spv/post.frag.spv");
 
     let window_rect = vk::Rect2D { 
         offset: vk::Offset2D { x: 0, y: 0 },
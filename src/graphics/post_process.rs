@@ -0,0 +1,415 @@
+use ash::vk::{self, AccessFlags, Extent3D, Format, ImageUsageFlags, MemoryPropertyFlags, PipelineStageFlags, ShaderStageFlags};
+use iron_oxide::graphics::{self, Buffer, SinlgeTimeCommands, VkBase};
+use winit::dpi::PhysicalSize;
+
+use super::hdr_target::HDR_FORMAT;
+use super::post_pipeline::create_post_pipeline;
+
+const LUT_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+    Bloom,
+    Fxaa,
+    Vignette,
+    ColorGrading,
+}
+
+impl PostEffect {
+    pub const ALL: [PostEffect; 4] = [Self::Bloom, Self::ColorGrading, Self::Fxaa, Self::Vignette];
+
+    fn fragment_shader(self) -> &'static [u8] {
+        match self {
+            Self::Bloom => include_bytes!("../../spv/bloom.frag.spv"),
+            Self::Fxaa => include_bytes!("../../spv/fxaa.frag.spv"),
+            Self::Vignette => include_bytes!("../../spv/vignette.frag.spv"),
+            Self::ColorGrading => include_bytes!("../../spv/color_grading.frag.spv"),
+        }
+    }
+}
+
+/// One HDR image of the ping-pong pair the effects render into.
+struct EffectTarget {
+    image: graphics::Image,
+    framebuffer: vk::Framebuffer,
+}
+
+/// Runs the enabled effects in order over the HDR world image, each one a fullscreen pass into the other ping-pong target.
+/// The last result is tonemapped onto the swapchain in subpass 0 of the main render pass.
+pub struct PostProcess {
+    /// Effects in the order they are applied, with their enabled flag.
+    pub effects: Vec<(PostEffect, bool)>,
+
+    sampler: vk::Sampler,
+    lut: graphics::Image,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Sampling the world image, ping-pong target 0 and ping-pong target 1.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+
+    effect_render_pass: vk::RenderPass,
+    targets: [EffectTarget; 2],
+    effect_pipelines: Vec<(PostEffect, vk::PipelineLayout, vk::Pipeline)>,
+
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    /// Descriptor set holding the output of the last effect recorded this frame.
+    final_input: usize,
+}
+
+impl PostProcess {
+    pub fn create(base: &VkBase, command_pool: vk::CommandPool, window_size: PhysicalSize<u32>, render_pass: vk::RenderPass, input: vk::ImageView) -> Self {
+        let device = &base.device;
+        let sampler = Self::create_sampler(device);
+        let descriptor_set_layout = Self::create_descriptor_set_layout(device);
+        let descriptor_pool = Self::create_descriptor_pool(device);
+
+        let layouts = [descriptor_set_layout; 3];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: layouts.len() as _,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info).unwrap() };
+
+        let cmd_buf = SinlgeTimeCommands::begin(base, command_pool);
+        let (lut, staging_buffer) = Self::create_lut(base, cmd_buf);
+        SinlgeTimeCommands::end(base, command_pool, cmd_buf);
+        staging_buffer.destroy(device);
+
+        let effect_render_pass = Self::create_effect_render_pass(device);
+        let targets = [
+            Self::create_target(base, effect_render_pass, window_size),
+            Self::create_target(base, effect_render_pass, window_size),
+        ];
+
+        let effect_pipelines = PostEffect::ALL.iter().map(|effect| {
+            let (layout, pipeline) = create_post_pipeline(device, window_size, effect_render_pass, &descriptor_set_layout, effect.fragment_shader());
+            (*effect, layout, pipeline)
+        }).collect();
+
+        let (pipeline_layout, pipeline) = create_post_pipeline(device, window_size, render_pass, &descriptor_set_layout, include_bytes!("../../spv/post.frag.spv"));
+
+        let post_process = Self {
+            effects: vec![(PostEffect::Bloom, true), (PostEffect::ColorGrading, false), (PostEffect::Fxaa, true), (PostEffect::Vignette, true)],
+            sampler,
+            lut,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            effect_render_pass,
+            targets,
+            effect_pipelines,
+            pipeline_layout,
+            pipeline,
+            final_input: 0,
+        };
+
+        post_process.set_input(device, input);
+        post_process
+    }
+
+    /// Reallocates the ping-pong targets for a new window size and points the chain at the new world image.
+    pub fn resize(&mut self, base: &VkBase, window_size: PhysicalSize<u32>, input: vk::ImageView) {
+        for target in &mut self.targets {
+            Self::destroy_target(&base.device, target);
+            *target = Self::create_target(base, self.effect_render_pass, window_size);
+        }
+
+        self.set_input(&base.device, input);
+    }
+
+    /// Flips an effect on or off and returns whether it is enabled now.
+    pub fn toggle(&mut self, effect: PostEffect) -> bool {
+        let entry = self.effects.iter_mut().find(|(other, _)| *other == effect).unwrap();
+        entry.1 = !entry.1;
+        entry.1
+    }
+
+    /// Reorders the effects. Effects missing from `order` keep their flag and run after the listed ones.
+    pub fn set_order(&mut self, order: &[PostEffect]) {
+        self.effects.sort_by_key(|(effect, _)| order.iter().position(|other| other == effect).unwrap_or(order.len()));
+    }
+
+    /// Points the chain at a new world image, needed whenever the HDR target is recreated.
+    pub fn set_input(&self, device: &ash::Device, input: vk::ImageView) {
+        let inputs = [input, self.targets[0].image.view, self.targets[1].image.view];
+
+        for (descriptor_set, input) in self.descriptor_sets.iter().zip(inputs) {
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: input,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: self.lut.view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+            ];
+
+            let descriptor_writes = [
+                vk::WriteDescriptorSet {
+                    dst_set: *descriptor_set,
+                    dst_binding: 0,
+                    dst_array_element: 0,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 1,
+                    p_image_info: &image_infos[0],
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: *descriptor_set,
+                    dst_binding: 1,
+                    dst_array_element: 0,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 1,
+                    p_image_info: &image_infos[1],
+                    ..Default::default()
+                },
+            ];
+
+            unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+        }
+    }
+
+    /// Records the enabled effects. Must be called outside of a render pass, before the main render pass begins.
+    /// Viewport and scissor are expected to be set to the window size already.
+    pub fn record_effects(&mut self, device: &ash::Device, cmd_buf: vk::CommandBuffer, window_size: PhysicalSize<u32>) {
+        let mut input = 0;
+
+        for (i, (effect, _)) in self.effects.iter().filter(|(_, enabled)| *enabled).enumerate() {
+            let output = i % 2;
+            let (_, pipeline_layout, pipeline) = self.effect_pipelines.iter().find(|(other, _, _)| other == effect).unwrap();
+
+            let render_pass_info = vk::RenderPassBeginInfo {
+                render_pass: self.effect_render_pass,
+                framebuffer: self.targets[output].framebuffer,
+                render_area: vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: vk::Extent2D { width: window_size.width, height: window_size.height } },
+                ..Default::default()
+            };
+
+            unsafe {
+                device.cmd_begin_render_pass(cmd_buf, &render_pass_info, vk::SubpassContents::INLINE);
+                device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, *pipeline);
+                device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, *pipeline_layout, 0, &[self.descriptor_sets[input]], &[]);
+                device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+                device.cmd_end_render_pass(cmd_buf);
+            }
+
+            input = output + 1;
+        }
+
+        self.final_input = input;
+    }
+
+    /// Tonemaps the result of the effect chain, recorded in subpass 0 of the main render pass.
+    pub fn record(&self, device: &ash::Device, cmd_buf: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[self.descriptor_sets[self.final_input]], &[]);
+            // passthrough.vert generates a fullscreen triangle from the vertex index
+            device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+        }
+    }
+
+    fn create_target(base: &VkBase, render_pass: vk::RenderPass, size: PhysicalSize<u32>) -> EffectTarget {
+        let extent = Extent3D { width: size.width, height: size.height, depth: 1 };
+        let mut image = graphics::Image::create(base, extent, HDR_FORMAT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::SAMPLED, MemoryPropertyFlags::DEVICE_LOCAL);
+        image.create_view(base, vk::ImageAspectFlags::COLOR);
+
+        let create_info = vk::FramebufferCreateInfo {
+            render_pass,
+            attachment_count: 1,
+            p_attachments: &image.view,
+            width: size.width,
+            height: size.height,
+            layers: 1,
+            ..Default::default()
+        };
+
+        let framebuffer = unsafe { base.device.create_framebuffer(&create_info, None).unwrap() };
+
+        EffectTarget { image, framebuffer }
+    }
+
+    fn destroy_target(device: &ash::Device, target: &mut EffectTarget) {
+        unsafe { device.destroy_framebuffer(target.framebuffer, None) };
+        target.image.destroy(device);
+    }
+
+    fn create_effect_render_pass(device: &ash::Device) -> vk::RenderPass {
+        // Every effect writes all pixels, so the old content is never loaded
+        let color_attachment = vk::AttachmentDescription {
+            format: HDR_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: vk::AttachmentDescriptionFlags::empty()
+        };
+
+        let color_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpass = vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            ..Default::default()
+        };
+
+        let dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: AccessFlags::SHADER_READ,
+                dst_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            }
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo {
+            attachment_count: 1,
+            p_attachments: &color_attachment,
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as _,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe { device.create_render_pass(&render_pass_info, None).unwrap() }
+    }
+
+    /// Builds the color grading LUT as a strip of `LUT_SIZE` slices, see `color_grading.frag` for the layout.
+    /// The grade is a slight warm tint with a bit more contrast and saturation.
+    fn create_lut(base: &VkBase, cmd_buf: vk::CommandBuffer) -> (graphics::Image, Buffer) {
+        let (width, height) = (LUT_SIZE * LUT_SIZE, LUT_SIZE);
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+        for g in 0..LUT_SIZE {
+            for b in 0..LUT_SIZE {
+                for r in 0..LUT_SIZE {
+                    let color = [r, g, b].map(|channel| channel as f32 / (LUT_SIZE - 1) as f32);
+                    let luma = color[0] * 0.2126 + color[1] * 0.7152 + color[2] * 0.0722;
+                    let tint = [1.04, 1.0, 0.94];
+
+                    for (channel, tint) in color.iter().zip(tint) {
+                        let saturated = luma + (channel - luma) * 1.1;
+                        let contrasted = (saturated - 0.5) * 1.08 + 0.5;
+                        pixels.push(((contrasted * tint).clamp(0.0, 1.0) * 255.0).round() as u8);
+                    }
+                    pixels.push(255);
+                }
+            }
+        }
+
+        let image_size = pixels.len() as u64;
+        let extent = Extent3D { width, height, depth: 1 };
+
+        let staging_buffer = Buffer::create(base, image_size, vk::BufferUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT);
+        let mapped_memory = staging_buffer.map_memory(&base.device, image_size, 0);
+        unsafe { std::ptr::copy_nonoverlapping(pixels.as_ptr(), mapped_memory as _, pixels.len()) };
+        staging_buffer.unmap_memory(&base.device);
+
+        let mut lut = graphics::Image::create(base, extent, Format::R8G8B8A8_UNORM, vk::ImageTiling::OPTIMAL, ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED, MemoryPropertyFlags::DEVICE_LOCAL);
+        lut.trasition_layout(base, cmd_buf, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        lut.copy_from_buffer(base, cmd_buf, &staging_buffer, extent, vk::ImageAspectFlags::COLOR);
+        lut.trasition_layout(base, cmd_buf, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        lut.create_view(base, vk::ImageAspectFlags::COLOR);
+
+        (lut, staging_buffer)
+    }
+
+    fn create_sampler(device: &ash::Device) -> vk::Sampler {
+        let create_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            compare_op: vk::CompareOp::ALWAYS,
+            min_lod: 0.0,
+            max_lod: 1.0,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: vk::FALSE,
+            ..Default::default()
+        };
+
+        unsafe { device.create_sampler(&create_info, None).unwrap() }
+    }
+
+    fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+        let bindings = [0, 1].map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        });
+
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as _,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+    }
+
+    fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
+        let pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 3 * 2,
+        };
+
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            pool_size_count: 1,
+            p_pool_sizes: &pool_size,
+            max_sets: 3,
+            ..Default::default()
+        };
+
+        unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for (_, layout, pipeline) in &self.effect_pipelines {
+                device.destroy_pipeline(*pipeline, None);
+                device.destroy_pipeline_layout(*layout, None);
+            }
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_render_pass(self.effect_render_pass, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+
+        for target in &mut self.targets {
+            Self::destroy_target(device, target);
+        }
+        self.lut.destroy(device);
+    }
+}
@@ -0,0 +1,120 @@
+use ash::vk;
+use iron_oxide::graphics::VkBase;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    /// Linear filtering that also blends between mip levels.
+    Trilinear,
+    /// Trilinear with up to 16x anisotropy, plain trilinear on devices without the feature.
+    Anisotropic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TextureWrap {
+    Repeat,
+    Clamp,
+}
+
+/// How a material's texture is filtered and wrapped.
+/// The world descriptor set holds one sampler per preset, instances pick theirs by `index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SamplerPreset {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl SamplerPreset {
+    pub const DEFAULT: Self = Self { filter: TextureFilter::Trilinear, wrap: TextureWrap::Repeat };
+    pub const COUNT: usize = 8;
+
+    const FILTERS: [TextureFilter; 4] = [TextureFilter::Nearest, TextureFilter::Linear, TextureFilter::Trilinear, TextureFilter::Anisotropic];
+    const WRAPS: [TextureWrap; 2] = [TextureWrap::Repeat, TextureWrap::Clamp];
+
+    pub const fn index(self) -> u32 {
+        self.filter as u32 * Self::WRAPS.len() as u32 + self.wrap as u32
+    }
+
+    pub const fn from_index(index: u32) -> Option<Self> {
+        if index as usize >= Self::COUNT {
+            return None;
+        }
+        let wraps = Self::WRAPS.len() as u32;
+        Some(Self { filter: Self::FILTERS[(index / wraps) as usize], wrap: Self::WRAPS[(index % wraps) as usize] })
+    }
+}
+
+impl Default for SamplerPreset {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Upper bound for the anisotropic preset, further clamped to `limits.max_sampler_anisotropy`.
+const MAX_ANISOTROPY: f32 = 16.0;
+
+/// One sampler per preset, in `SamplerPreset::index` order.
+/// `VkBase` has to enable the `samplerAnisotropy` device feature whenever the device supports it.
+pub fn create_material_samplers(base: &VkBase) -> [vk::Sampler; SamplerPreset::COUNT] {
+    let features = unsafe { base.instance.get_physical_device_features(base.physical_device) };
+    let limits = unsafe { base.instance.get_physical_device_properties(base.physical_device).limits };
+    // Devices without the feature draw the anisotropic preset like trilinear
+    let max_anisotropy = (features.sampler_anisotropy == vk::TRUE).then(|| MAX_ANISOTROPY.min(limits.max_sampler_anisotropy));
+    if max_anisotropy.is_none() {
+        log::warn!("sampler anisotropy is not supported, anisotropic textures fall back to trilinear filtering");
+    }
+
+    std::array::from_fn(|index| create_sampler(&base.device, SamplerPreset::from_index(index as u32).unwrap(), max_anisotropy))
+}
+
+fn create_sampler(device: &ash::Device, preset: SamplerPreset, max_anisotropy: Option<f32>) -> vk::Sampler {
+    let (filter, mipmap_mode) = match preset.filter {
+        TextureFilter::Nearest => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+        TextureFilter::Linear => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+        TextureFilter::Trilinear | TextureFilter::Anisotropic => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+    };
+    let address_mode = match preset.wrap {
+        TextureWrap::Repeat => vk::SamplerAddressMode::REPEAT,
+        TextureWrap::Clamp => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    };
+    let max_anisotropy = max_anisotropy.filter(|_| preset.filter == TextureFilter::Anisotropic);
+
+    let create_info = vk::SamplerCreateInfo {
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_mode,
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        mip_lod_bias: 0.0,
+        anisotropy_enable: max_anisotropy.is_some() as _,
+        max_anisotropy: max_anisotropy.unwrap_or(1.0),
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        min_lod: 0.0,
+        max_lod: vk::LOD_CLAMP_NONE,
+        border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    unsafe { device.create_sampler(&create_info, None).unwrap() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SamplerPreset, TextureFilter, TextureWrap};
+
+    #[test]
+    fn index_round_trips() {
+        for index in 0..SamplerPreset::COUNT as u32 {
+            assert_eq!(SamplerPreset::from_index(index).unwrap().index(), index);
+        }
+        assert_eq!(SamplerPreset::from_index(SamplerPreset::COUNT as u32), None);
+        assert_eq!(SamplerPreset::from_index(SamplerPreset::DEFAULT.index()), Some(SamplerPreset::DEFAULT));
+
+        let anisotropic_clamp = SamplerPreset { filter: TextureFilter::Anisotropic, wrap: TextureWrap::Clamp };
+        assert_eq!(anisotropic_clamp.index(), SamplerPreset::COUNT as u32 - 1);
+    }
+}
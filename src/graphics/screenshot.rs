@@ -0,0 +1,110 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Error},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH}
+};
+use ash::vk::{self, MemoryPropertyFlags};
+use iron_oxide::graphics::{Buffer, VkBase};
+use winit::dpi::PhysicalSize;
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenshotMode {
+    WithUi,
+    WorldOnly,
+}
+
+pub struct PendingScreenshot {
+    pub mode: ScreenshotMode,
+    pub image: vk::Image,
+    pub buffer: Buffer,
+    pub size: PhysicalSize<u32>,
+}
+
+impl PendingScreenshot {
+    pub fn create(base: &VkBase, mode: ScreenshotMode, image: vk::Image, size: PhysicalSize<u32>) -> Self {
+        let buffer_size = size.width as u64 * size.height as u64 * 4;
+        let buffer = Buffer::create(base, buffer_size, vk::BufferUsageFlags::TRANSFER_DST, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT);
+
+        Self { mode, image, buffer, size }
+    }
+
+    /// Records the copy of a presentable image into the readback buffer, leaving the image in `PRESENT_SRC_KHR`.
+    pub fn record_copy(&self, device: &ash::Device, cmd_buf: vk::CommandBuffer) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let to_transfer = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            old_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.image,
+            subresource_range,
+            ..Default::default()
+        };
+
+        let to_present = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..to_transfer
+        };
+
+        let region = vk::BufferImageCopy {
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_extent: vk::Extent3D { width: self.size.width, height: self.size.height, depth: 1 },
+            ..Default::default()
+        };
+
+        unsafe {
+            device.cmd_pipeline_barrier(cmd_buf, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[], &[], &[to_transfer]);
+            device.cmd_copy_image_to_buffer(cmd_buf, self.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.buffer.inner, &[region]);
+            device.cmd_pipeline_barrier(cmd_buf, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::DependencyFlags::empty(), &[], &[], &[to_present]);
+        }
+    }
+
+    /// Reads the copied pixels back. The frame that recorded the copy must have finished.
+    pub fn finish(self, device: &ash::Device) -> (PhysicalSize<u32>, Vec<u8>) {
+        let size = self.buffer.size;
+        let mut pixels = vec![0u8; size as usize];
+        let mapped_memory = self.buffer.map_memory(device, size, 0);
+        unsafe { std::ptr::copy_nonoverlapping(mapped_memory as *const u8, pixels.as_mut_ptr(), size as usize) };
+        self.buffer.unmap_memory(device);
+        self.buffer.destroy(device);
+
+        (self.size, pixels)
+    }
+}
+
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), Error> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// Writes the pixels to `screenshots/screenshot_<unix time in ms>.png` and returns the path.
+pub fn save_screenshot(size: PhysicalSize<u32>, pixels: &[u8]) -> Result<PathBuf, Error> {
+    fs::create_dir_all(SCREENSHOT_DIR)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let path = PathBuf::from(format!("{SCREENSHOT_DIR}/screenshot_{timestamp}.png"));
+    write_png(&path, size.width, size.height, pixels)?;
+    Ok(path)
+}
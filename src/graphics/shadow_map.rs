@@ -0,0 +1,219 @@
+use ash::vk::{self, Extent3D, Format, ImageUsageFlags, MemoryPropertyFlags, PipelineStageFlags};
+use cgmath::{ortho, EuclideanSpace, Matrix4, Point3, Transform, Vector3};
+use iron_oxide::{graphics::{self, VkBase}, primitives::Vec3};
+
+use super::shadow_pipeline::create_shadow_pipeline;
+
+pub const SHADOW_FORMAT: Format = Format::D32_SFLOAT;
+
+// cgmath builds OpenGL style matrices with a depth range of -1..1, Vulkan expects 0..1
+pub const DEPTH_CORRECTION: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    pub depth_bias_constant: f32,
+    pub depth_bias_slope: f32,
+    /// World space offset along the surface normal before the shadow lookup, against acne on steep faces.
+    pub normal_bias: f32,
+    /// Half size of the area around the camera that receives shadows, in world units.
+    pub extent: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            depth_bias_constant: 1.25,
+            depth_bias_slope: 1.75,
+            normal_bias: 0.02,
+            extent: 12.0,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Orthographic view projection of the sun, centered on `focus`.
+    /// The center is snapped to whole texels so shadow edges do not shimmer while the camera moves.
+    pub fn light_view_proj(&self, sun_direction: Vec3, focus: Vec3) -> Matrix4<f32> {
+        let direction: Vector3<f32> = sun_direction.normalize().into();
+        let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let view = Matrix4::look_to_rh(Point3::origin(), direction, up);
+
+        let texel = self.extent * 2.0 / self.resolution as f32;
+        let center = view.transform_point(Point3::new(focus.x, focus.y, focus.z));
+        let (x, y) = ((center.x / texel).round() * texel, (center.y / texel).round() * texel);
+        let distance = -center.z;
+
+        let proj = ortho(x - self.extent, x + self.extent, y - self.extent, y + self.extent, distance - self.extent * 2.0, distance + self.extent * 2.0);
+        DEPTH_CORRECTION * proj * view
+    }
+}
+
+/// Depth only render target for the sun, drawn in its own render pass before the world is rendered.
+pub struct ShadowMap {
+    pub settings: ShadowSettings,
+    pub image: graphics::Image,
+    pub sampler: vk::Sampler,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+impl ShadowMap {
+    pub fn create(base: &VkBase, settings: ShadowSettings, descriptor_set_layout: vk::DescriptorSetLayout) -> Self {
+        let extent = Extent3D { width: settings.resolution, height: settings.resolution, depth: 1 };
+        let mut image = graphics::Image::create(base, extent, SHADOW_FORMAT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ImageUsageFlags::SAMPLED, MemoryPropertyFlags::DEVICE_LOCAL);
+        image.create_view(base, vk::ImageAspectFlags::DEPTH);
+
+        let render_pass = Self::create_render_pass(&base.device);
+
+        let create_info = vk::FramebufferCreateInfo {
+            render_pass,
+            attachment_count: 1,
+            p_attachments: &image.view,
+            width: settings.resolution,
+            height: settings.resolution,
+            layers: 1,
+            ..Default::default()
+        };
+        let framebuffer = unsafe { base.device.create_framebuffer(&create_info, None).unwrap() };
+
+        let sampler = Self::create_sampler(&base.device);
+        let (pipeline_layout, pipeline) = create_shadow_pipeline(&base.device, &settings, render_pass, descriptor_set_layout);
+
+        Self { settings, image, sampler, render_pass, framebuffer, pipeline_layout, pipeline }
+    }
+
+    fn create_render_pass(device: &ash::Device) -> vk::RenderPass {
+        let depth_attachment = vk::AttachmentDescription {
+            format: SHADOW_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            flags: vk::AttachmentDescriptionFlags::empty(),
+        };
+
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpass = vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            ..Default::default()
+        };
+
+        let dependencies = [
+            // The previous frame must be done sampling before the map is cleared
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::SHADER_READ,
+                dst_stage_mask: PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::BY_REGION,
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::BY_REGION,
+            },
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo {
+            attachment_count: 1,
+            p_attachments: &depth_attachment,
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as _,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe { device.create_render_pass(&render_pass_info, None).unwrap() }
+    }
+
+    fn create_sampler(device: &ash::Device) -> vk::Sampler {
+        // Hardware depth comparison, linear filtering gives an extra 2x2 PCF on top of the one in the shader.
+        // Lookups outside the map hit the white border and count as lit.
+        let create_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            compare_enable: vk::TRUE,
+            compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            min_lod: 0.0,
+            max_lod: 1.0,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            unnormalized_coordinates: vk::FALSE,
+            ..Default::default()
+        };
+
+        unsafe { device.create_sampler(&create_info, None).unwrap() }
+    }
+
+    pub fn record(&self, device: &ash::Device, cmd_buf: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, draw: impl FnOnce(vk::CommandBuffer)) {
+        let clear_value = vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } };
+        let extent = vk::Extent2D { width: self.settings.resolution, height: self.settings.resolution };
+
+        let render_pass_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: self.framebuffer,
+            render_area: vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent },
+            clear_value_count: 1,
+            p_clear_values: &clear_value,
+            ..Default::default()
+        };
+
+        let view_port = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0
+        };
+
+        unsafe {
+            device.cmd_begin_render_pass(cmd_buf, &render_pass_info, vk::SubpassContents::INLINE);
+            device.cmd_set_viewport(cmd_buf, 0, &[view_port]);
+            device.cmd_set_scissor(cmd_buf, 0, &[vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent }]);
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[descriptor_set], &[]);
+            draw(cmd_buf);
+            device.cmd_end_render_pass(cmd_buf);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_framebuffer(self.framebuffer, None);
+            device.destroy_render_pass(self.render_pass, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+        self.image.destroy(device);
+    }
+}
@@ -0,0 +1,130 @@
+
+use ash::vk::{self};
+use super::{shader_modul, Vertex};
+
+use super::ShadowSettings;
+
+pub fn create_shadow_pipeline(device: &ash::Device, settings: &ShadowSettings, render_pass: vk::RenderPass, descriptor_set_layout: vk::DescriptorSetLayout) -> (vk::PipelineLayout, vk::Pipeline) {
+    let vertex_shader_buff= include_bytes!("../../spv/shadow.vert.spv");
+
+    let window_rect = vk::Rect2D { 
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent: vk::Extent2D { width: settings.resolution, height: settings.resolution },
+    };
+
+    let vertex_shader_module = shader_modul::create_shader_modul(device, vertex_shader_buff);
+
+    let vertex_stage_info = vk::PipelineShaderStageCreateInfo {
+        s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+        stage: vk::ShaderStageFlags::VERTEX,
+        module: vertex_shader_module,
+        p_name:  c"main".as_ptr(),
+        ..Default::default()
+    };
+
+    // Depth only, no fragment stage
+    let shader_stage = [vertex_stage_info];
+
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo {
+        vertex_binding_description_count: Vertex::GET_BINDING_DESCRIPTION.len() as _,
+        vertex_attribute_description_count: Vertex::GET_ATTRIBUTE_DESCRIPTIONS.len() as _,
+        p_vertex_binding_descriptions: Vertex::GET_BINDING_DESCRIPTION.as_ptr(),
+        p_vertex_attribute_descriptions: Vertex::GET_ATTRIBUTE_DESCRIPTIONS.as_ptr(),
+        ..Default::default()
+    };
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        primitive_restart_enable: vk::FALSE,
+        ..Default::default()
+    };
+
+    let dynamic_states = [ vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR ];
+    
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+        dynamic_state_count: dynamic_states.len() as _,
+        p_dynamic_states: dynamic_states.as_ptr(),
+        ..Default::default()
+    };
+
+    let view_port = vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: settings.resolution as _,
+        height: settings.resolution as _,
+        min_depth: 0.0,
+        max_depth: 1.0
+    };
+
+    let view_ports_state = vk::PipelineViewportStateCreateInfo {
+        viewport_count: 1,
+        p_viewports: &view_port as _,
+        scissor_count: 1,
+        p_scissors: &window_rect as _,
+        ..Default::default()
+    };
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo {
+        depth_clamp_enable: vk::FALSE,
+        rasterizer_discard_enable: vk::FALSE,
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::NONE,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        depth_bias_enable: vk::TRUE,
+        depth_bias_constant_factor: settings.depth_bias_constant,
+        depth_bias_slope_factor: settings.depth_bias_slope,
+        line_width: 1.0,
+        ..Default::default()
+    };
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo {
+        sample_shading_enable: vk::FALSE,
+        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        min_sample_shading: 1.0,
+        ..Default::default()
+    };
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+        set_layout_count: 1,
+        p_set_layouts: &descriptor_set_layout,
+        ..Default::default()
+    };
+
+    let pipeline_layout = unsafe { device.create_pipeline_layout(&pipeline_layout_info, None).unwrap() };
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: vk::TRUE,
+        depth_write_enable: vk::TRUE,
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        depth_bounds_test_enable: vk::FALSE,
+        stencil_test_enable: vk::FALSE,
+        min_depth_bounds: 0.0,
+        max_depth_bounds: 1.0,
+        ..Default::default()
+    };
+
+    let main_create_info = vk::GraphicsPipelineCreateInfo {
+        stage_count: shader_stage.len() as _,
+        p_stages: shader_stage.as_ptr(),
+        p_vertex_input_state: &vertex_input_info,
+        p_input_assembly_state: &input_assembly,
+        p_viewport_state: &view_ports_state,
+        p_rasterization_state: &rasterizer,
+        p_multisample_state: &multisampling,
+        p_depth_stencil_state: &depth_stencil,
+        p_dynamic_state: &dynamic_state,
+        layout: pipeline_layout,
+        render_pass,
+        subpass: 0,
+        base_pipeline_index: -1,
+        ..Default::default()
+    };
+
+    let pipelines = unsafe { device.create_graphics_pipelines(vk::PipelineCache::null(), &[main_create_info], None).unwrap()[0] };
+
+    unsafe {
+        device.destroy_shader_module(vertex_shader_module, None);
+    }
+
+    (pipeline_layout, pipelines)
+}
\ No newline at end of file
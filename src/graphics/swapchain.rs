@@ -7,6 +7,7 @@ pub struct Swapchain {
     pub inner: SwapchainKHR,
     pub surface_loader: surface::Instance,
     pub surface: SurfaceKHR,
+    pub images: Vec<vk::Image>,
     pub image_views: Vec<ImageView>,
     pub capabilities: SurfaceCapabilitiesKHR,
     pub format: SurfaceFormatKHR,
@@ -25,7 +26,7 @@ impl Swapchain {
             vk::CompositeAlphaFlagsKHR::INHERIT
         };
         let swapchain = Self::create_swap_chain(window_size, surface, &loader, &capabilities, composite_alpha, format, present_mode, base.queue_family_index);
-        let image_views = Self::create_image_views(&loader, swapchain, &base.device, format.format);
+        let (images, image_views) = Self::create_image_views(&loader, swapchain, &base.device, format.format);
 
         let framebuffers = vec![Framebuffer::null(); image_views.len()];
 
@@ -34,6 +35,7 @@ impl Swapchain {
             inner: swapchain,
             surface_loader,
             surface,
+            images,
             image_views,
             capabilities,
             format,
@@ -79,7 +81,7 @@ impl Swapchain {
             image_color_space: self.format.color_space,
             image_extent,
             image_array_layers: 1,
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
             image_sharing_mode: vk::SharingMode::EXCLUSIVE,
             queue_family_index_count: 1,
             p_queue_family_indices: &base.queue_family_index,
@@ -101,7 +103,7 @@ impl Swapchain {
             self.inner = new;
         } 
 
-        self.image_views = Self::create_image_views(&self.loader, self.inner, &base.device, self.format.format);
+        (self.images, self.image_views) = Self::create_image_views(&self.loader, self.inner, &base.device, self.format.format);
         self.create_framebuffer(base, render_pass, attachment, window_size);
     }
 
@@ -134,7 +136,7 @@ impl Swapchain {
             image_color_space: format.color_space,
             image_extent,
             image_array_layers: 1,
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
             image_sharing_mode: vk::SharingMode::EXCLUSIVE,
             queue_family_index_count: 1,
             p_queue_family_indices: &queue_family_index,
@@ -149,11 +151,11 @@ impl Swapchain {
 
     }
 
-    fn create_image_views(swapchain_loader: &swapchain::Device, swapchain: SwapchainKHR, device: &ash::Device, format: Format) -> Vec<vk::ImageView> {
+    fn create_image_views(swapchain_loader: &swapchain::Device, swapchain: SwapchainKHR, device: &ash::Device, format: Format) -> (Vec<vk::Image>, Vec<vk::ImageView>) {
         let present_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
         let mut present_image_views = Vec::with_capacity(present_images.len());
 
-        for present_image in present_images {
+        for &present_image in &present_images {
             let create_info = vk::ImageViewCreateInfo {
                 image: present_image,
                 view_type: vk::ImageViewType::TYPE_2D,
@@ -170,7 +172,7 @@ impl Swapchain {
            present_image_views.push(unsafe { device.create_image_view(&create_info, None).unwrap() });
         }
 
-        present_image_views
+        (present_images, present_image_views)
     }
 
     pub fn destroy(&mut self, device: &Device) {
@@ -0,0 +1,354 @@
+use std::{fs, io::Error, ptr};
+use ash::vk::{self, AccessFlags, Extent3D, Format, ImageUsageFlags, MemoryPropertyFlags, PipelineStageFlags};
+use iron_oxide::graphics::{self, Buffer, SinlgeTimeCommands, VkBase};
+
+use super::attachment::find_memory_type;
+use super::TextureSource;
+
+/// Every layer is scaled to this size on upload, a texture array needs all layers to match.
+pub const TEXTURE_SIZE: u32 = 256;
+pub const MAX_TEXTURE_LAYERS: u32 = 64;
+/// Layers below this are compiled in, the others are loaded at runtime and do not outlive the session.
+pub const BUILTIN_TEXTURE_LAYERS: u32 = 1;
+const MIP_LEVELS: u32 = TEXTURE_SIZE.ilog2() + 1;
+const TEXTURE_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+// Layers whose image cannot be loaded show up magenta instead of failing the whole frame
+const MISSING_TEXTURE: [u8; 4] = [255, 0, 255, 255];
+
+/// Images the world textures are loaded from, the index of a source is its layer in the `TextureArray`.
+pub struct TextureRegistry {
+    sources: Vec<TextureSource>,
+}
+
+impl TextureRegistry {
+    /// Layer 0 is the compiled-in default texture.
+    pub fn new() -> Self {
+        Self { sources: vec![TextureSource::Embedded(include_bytes!("../../textures/texture.png").to_vec())] }
+    }
+
+    /// Layer of `source`, which is uploaded before the next frame if it is new. `None` once every layer is taken.
+    pub fn add(&mut self, source: TextureSource) -> Option<u32> {
+        if let Some(layer) = self.sources.iter().position(|known| *known == source) {
+            return Some(layer as u32);
+        }
+
+        if self.sources.len() as u32 >= MAX_TEXTURE_LAYERS {
+            return None;
+        }
+
+        self.sources.push(source);
+        Some(self.sources.len() as u32 - 1)
+    }
+
+    pub fn sources(&self) -> &[TextureSource] {
+        &self.sources
+    }
+}
+
+impl Default for TextureRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mipmapped 2D array image holding every texture of the `TextureRegistry`.
+pub struct TextureArray {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    /// All layers, bound to the world pipeline.
+    pub view: vk::ImageView,
+    /// Only the default texture, for the UI which samples plain 2D images.
+    pub default_view: vk::ImageView,
+    uploaded: usize,
+}
+
+impl TextureArray {
+    pub fn create(base: &VkBase, command_pool: vk::CommandPool) -> Self {
+        let device = &base.device;
+
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format: TEXTURE_FORMAT,
+            extent: Extent3D { width: TEXTURE_SIZE, height: TEXTURE_SIZE, depth: 1 },
+            mip_levels: MIP_LEVELS,
+            array_layers: MAX_TEXTURE_LAYERS,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+
+        let image = unsafe { device.create_image(&image_info, None).unwrap() };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        let alloc_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index: find_memory_type(base, requirements.memory_type_bits, MemoryPropertyFlags::DEVICE_LOCAL),
+            ..Default::default()
+        };
+
+        let memory = unsafe { device.allocate_memory(&alloc_info, None).unwrap() };
+        unsafe { device.bind_image_memory(image, memory, 0).unwrap() };
+
+        let view = Self::create_view(device, image, vk::ImageViewType::TYPE_2D_ARRAY, MAX_TEXTURE_LAYERS);
+        let default_view = Self::create_view(device, image, vk::ImageViewType::TYPE_2D, 1);
+
+        // Layers that are not loaded yet are never sampled, but the descriptor expects one layout for the whole image
+        let cmd_buf = SinlgeTimeCommands::begin(base, command_pool);
+        layer_barrier(device, cmd_buf, image, 0..MAX_TEXTURE_LAYERS, 0..MIP_LEVELS, vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        SinlgeTimeCommands::end(base, command_pool, cmd_buf);
+
+        Self { image, memory, view, default_view, uploaded: 0 }
+    }
+
+    fn create_view(device: &ash::Device, image: vk::Image, view_type: vk::ImageViewType, layer_count: u32) -> vk::ImageView {
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type,
+            format: TEXTURE_FORMAT,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count,
+            },
+            ..Default::default()
+        };
+
+        unsafe { device.create_image_view(&view_info, None).unwrap() }
+    }
+
+    /// Number of registry sources that are already in the array.
+    pub fn uploaded(&self) -> usize {
+        self.uploaded
+    }
+
+    /// Loads every source past the already uploaded ones into its layer and builds its mip chain.
+    /// Layers that are being written are not referenced by any instance yet, so frames in flight are unaffected.
+    pub fn upload(&mut self, base: &VkBase, command_pool: vk::CommandPool, sources: &[TextureSource]) {
+        if sources.len() <= self.uploaded {
+            return;
+        }
+
+        let cmd_buf = SinlgeTimeCommands::begin(base, command_pool);
+        let mut staging = Vec::new();
+
+        for (layer, source) in sources.iter().enumerate().skip(self.uploaded) {
+            let (width, height, pixels) = load_rgba_or_missing(source);
+            staging.push(self.record_layer_upload(base, cmd_buf, layer as u32, width, height, &pixels));
+        }
+
+        SinlgeTimeCommands::end(base, command_pool, cmd_buf);
+        for (mut image, buffer) in staging {
+            image.destroy(&base.device);
+            buffer.destroy(&base.device);
+        }
+
+        self.uploaded = sources.len();
+    }
+
+    /// Copies the pixels into a temporary image and blits that into mip 0 of `layer`, which scales it to `TEXTURE_SIZE`.
+    /// Returns the temporary image and buffer, they must live until `cmd_buf` has finished.
+    fn record_layer_upload(&self, base: &VkBase, cmd_buf: vk::CommandBuffer, layer: u32, width: u32, height: u32, pixels: &[u8]) -> (graphics::Image, Buffer) {
+        let device = &base.device;
+        let size = pixels.len() as u64;
+        let extent = Extent3D { width, height, depth: 1 };
+
+        let staging_buffer = Buffer::create(base, size, vk::BufferUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT);
+        let mapped_memory = staging_buffer.map_memory(device, size, 0);
+        unsafe { ptr::copy_nonoverlapping(pixels.as_ptr(), mapped_memory as _, pixels.len()) };
+        staging_buffer.unmap_memory(device);
+
+        let mut source = graphics::Image::create(base, extent, TEXTURE_FORMAT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::DEVICE_LOCAL);
+        source.trasition_layout(base, cmd_buf, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        source.copy_from_buffer(base, cmd_buf, &staging_buffer, extent, vk::ImageAspectFlags::COLOR);
+        layer_barrier(device, cmd_buf, source.inner, 0..1, 0..1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+        layer_barrier(device, cmd_buf, self.image, layer..layer + 1, 0..MIP_LEVELS, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        blit(device, cmd_buf, source.inner, 0, 0, (width, height), self.image, layer, 0, (TEXTURE_SIZE, TEXTURE_SIZE));
+        record_mip_blits(device, cmd_buf, self.image, layer, (TEXTURE_SIZE, TEXTURE_SIZE), MIP_LEVELS);
+
+        (source, staging_buffer)
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.default_view, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// Fills mips 1.. of `layer` by repeatedly halving mip 0 with linear blits.
+/// Expects every mip of the layer in `TRANSFER_DST_OPTIMAL` and leaves them in `SHADER_READ_ONLY_OPTIMAL`.
+pub fn record_mip_blits(device: &ash::Device, cmd_buf: vk::CommandBuffer, image: vk::Image, layer: u32, size: (u32, u32), mip_levels: u32) {
+    let mut size = size;
+
+    for mip in 1..mip_levels {
+        let next = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+        layer_barrier(device, cmd_buf, image, layer..layer + 1, mip - 1..mip, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        blit(device, cmd_buf, image, layer, mip - 1, size, image, layer, mip, next);
+        layer_barrier(device, cmd_buf, image, layer..layer + 1, mip - 1..mip, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        size = next;
+    }
+
+    layer_barrier(device, cmd_buf, image, layer..layer + 1, mip_levels - 1..mip_levels, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn blit(device: &ash::Device, cmd_buf: vk::CommandBuffer, src: vk::Image, src_layer: u32, src_mip: u32, src_size: (u32, u32), dst: vk::Image, dst_layer: u32, dst_mip: u32, dst_size: (u32, u32)) {
+    let subresource = |layer, mip| vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: mip,
+        base_array_layer: layer,
+        layer_count: 1,
+    };
+
+    let region = vk::ImageBlit {
+        src_subresource: subresource(src_layer, src_mip),
+        src_offsets: [vk::Offset3D::default(), vk::Offset3D { x: src_size.0 as _, y: src_size.1 as _, z: 1 }],
+        dst_subresource: subresource(dst_layer, dst_mip),
+        dst_offsets: [vk::Offset3D::default(), vk::Offset3D { x: dst_size.0 as _, y: dst_size.1 as _, z: 1 }],
+    };
+
+    unsafe { device.cmd_blit_image(cmd_buf, src, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, dst, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region], vk::Filter::LINEAR) };
+}
+
+fn layer_barrier(device: &ash::Device, cmd_buf: vk::CommandBuffer, image: vk::Image, layers: std::ops::Range<u32>, mips: std::ops::Range<u32>, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) {
+    let (src_access_mask, src_stage) = match old_layout {
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (AccessFlags::TRANSFER_WRITE, PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (AccessFlags::TRANSFER_READ, PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (AccessFlags::SHADER_READ, PipelineStageFlags::FRAGMENT_SHADER),
+        _ => (AccessFlags::empty(), PipelineStageFlags::TOP_OF_PIPE),
+    };
+    let (dst_access_mask, dst_stage) = match new_layout {
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (AccessFlags::TRANSFER_WRITE, PipelineStageFlags::TRANSFER),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (AccessFlags::TRANSFER_READ, PipelineStageFlags::TRANSFER),
+        _ => (AccessFlags::SHADER_READ, PipelineStageFlags::FRAGMENT_SHADER),
+    };
+
+    let barrier = vk::ImageMemoryBarrier {
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: mips.start,
+            level_count: mips.len() as _,
+            base_array_layer: layers.start,
+            layer_count: layers.len() as _,
+        },
+        ..Default::default()
+    };
+
+    unsafe { device.cmd_pipeline_barrier(cmd_buf, src_stage, dst_stage, vk::DependencyFlags::empty(), &[], &[], &[barrier]) };
+}
+
+fn load_rgba_or_missing(source: &TextureSource) -> (u32, u32, Vec<u8>) {
+    load_rgba(source).unwrap_or_else(|err| {
+        log::error!("failed to load texture {source}: {err}");
+        (1, 1, MISSING_TEXTURE.to_vec())
+    })
+}
+
+/// Decodes a png into tightly packed RGBA8 rows.
+fn load_rgba(source: &TextureSource) -> Result<(u32, u32, Vec<u8>), Error> {
+    let bytes = match source {
+        TextureSource::File(path) => fs::read(path)?,
+        TextureSource::Embedded(bytes) => bytes.clone(),
+    };
+
+    let mut decoder = png::Decoder::new(bytes.as_slice());
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err(Error::other("indexed png was not expanded")),
+    };
+
+    Ok((info.width, info.height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::{load_rgba, load_rgba_or_missing, TextureRegistry, TextureSource, MAX_TEXTURE_LAYERS, MISSING_TEXTURE};
+
+    // 2x1 image, `palette` and `trns` only for indexed images
+    fn encode(color_type: png::ColorType, data: &[u8], palette: Option<&[u8]>, trns: Option<&[u8]>) -> TextureSource {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(palette) = palette {
+            encoder.set_palette(palette);
+        }
+        if let Some(trns) = trns {
+            encoder.set_trns(trns);
+        }
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        TextureSource::Embedded(bytes)
+    }
+
+    fn pixels(source: &TextureSource) -> Vec<u8> {
+        let (width, height, pixels) = load_rgba(source).unwrap();
+        assert_eq!((width, height), (2, 1));
+        pixels
+    }
+
+    #[test]
+    fn every_color_type_becomes_rgba() {
+        assert_eq!(pixels(&encode(png::ColorType::Grayscale, &[0, 200], None, None)), [0, 0, 0, 255, 200, 200, 200, 255]);
+        assert_eq!(pixels(&encode(png::ColorType::GrayscaleAlpha, &[10, 20, 200, 255], None, None)), [10, 10, 10, 20, 200, 200, 200, 255]);
+        assert_eq!(pixels(&encode(png::ColorType::Rgb, &[1, 2, 3, 4, 5, 6], None, None)), [1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(pixels(&encode(png::ColorType::Rgba, &[1, 2, 3, 4, 5, 6, 7, 8], None, None)), [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn indexed_images_are_expanded() {
+        let palette = [255, 0, 0, 0, 0, 255];
+        assert_eq!(pixels(&encode(png::ColorType::Indexed, &[1, 0], Some(&palette), None)), [0, 0, 255, 255, 255, 0, 0, 255]);
+        assert_eq!(pixels(&encode(png::ColorType::Indexed, &[1, 0], Some(&palette), Some(&[128]))), [0, 0, 255, 255, 255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn broken_images_are_magenta() {
+        let missing = TextureSource::File(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("textures/does_not_exist.png"));
+        assert_eq!(load_rgba_or_missing(&missing), (1, 1, MISSING_TEXTURE.to_vec()));
+
+        let not_png = TextureSource::Embedded(b"not a png".to_vec());
+        assert!(load_rgba(&not_png).is_err());
+        assert_eq!(load_rgba_or_missing(&not_png), (1, 1, MISSING_TEXTURE.to_vec()));
+    }
+
+    #[test]
+    fn registry_runs_out_of_layers() {
+        let mut registry = TextureRegistry::new();
+        let source = |index: u32| TextureSource::File(PathBuf::from(format!("texture_{index}.png")));
+
+        for index in 1..MAX_TEXTURE_LAYERS {
+            assert_eq!(registry.add(source(index)), Some(index));
+        }
+        assert_eq!(registry.add(source(MAX_TEXTURE_LAYERS)), None);
+        // Known sources keep their layer even when the array is full
+        assert_eq!(registry.add(source(5)), Some(5));
+        assert_eq!(registry.sources().len(), MAX_TEXTURE_LAYERS as usize);
+    }
+}
@@ -2,12 +2,33 @@
 
 use cgmath::Matrix4;
 
-#[repr(align(16))]
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PointLightData {
+    /// xyz position, w radius
+    pub position: [f32; 4],
+    /// rgb color premultiplied with the intensity
+    pub color: [f32; 4],
+}
+
+// Only vec4 sized members so the std140 layout in the shaders matches without manual padding
+#[repr(C, align(16))]
 pub struct UniformBufferObject {
     pub view_proj: Matrix4<f32>,
+    pub camera_position: [f32; 4],
+    pub sun_direction: [f32; 4],
+    pub sun_color: [f32; 4],
+    pub ambient_color: [f32; 4],
+    pub point_lights: [PointLightData; MAX_POINT_LIGHTS],
+    pub point_light_count: [u32; 4],
+    pub light_view_proj: Matrix4<f32>,
+    /// x shadow map texel size in uv, y normal bias
+    pub shadow_params: [f32; 4],
 }
 
 #[repr(align(16))]
 pub struct UiUniformBufferObject {
     pub view_proj: Matrix4<f32>,
-} 
\ No newline at end of file
+}
@@ -1,8 +1,10 @@
 use std::mem::offset_of;
 
-use cgmath::{Matrix4, Vector2, Vector3};
+use cgmath::{Vector2, Vector3};
 use ash::vk;
 
+use super::WorldInstance;
+
 
 #[derive(Debug, Clone, PartialEq)]
 #[repr(C)]
@@ -22,12 +24,12 @@ impl Vertex {
         },
         vk::VertexInputBindingDescription {
             binding: 1,
-            stride: std::mem::size_of::<Matrix4<f32>>() as _,
+            stride: std::mem::size_of::<WorldInstance>() as _,
             input_rate: vk::VertexInputRate::INSTANCE,
         }
     ];
 
-    pub const GET_ATTRIBUTE_DESCRIPTIONS: [vk::VertexInputAttributeDescription; 8] = [
+    pub const GET_ATTRIBUTE_DESCRIPTIONS: [vk::VertexInputAttributeDescription; 13] = [
         vk::VertexInputAttributeDescription {
             binding: 0,
             location: 0,
@@ -77,6 +79,36 @@ impl Vertex {
             format: vk::Format::R32G32B32A32_SFLOAT,
             offset: 48,
         },
+        vk::VertexInputAttributeDescription {
+            binding: 1,
+            location: 8,
+            format: vk::Format::R32_UINT,
+            offset: offset_of!(WorldInstance, flags) as _,
+        },
+        vk::VertexInputAttributeDescription {
+            binding: 1,
+            location: 9,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset_of!(WorldInstance, base_color) as _,
+        },
+        vk::VertexInputAttributeDescription {
+            binding: 1,
+            location: 10,
+            format: vk::Format::R32_SINT,
+            offset: offset_of!(WorldInstance, texture_layer) as _,
+        },
+        vk::VertexInputAttributeDescription {
+            binding: 1,
+            location: 11,
+            format: vk::Format::R32_SFLOAT,
+            offset: offset_of!(WorldInstance, roughness) as _,
+        },
+        vk::VertexInputAttributeDescription {
+            binding: 1,
+            location: 12,
+            format: vk::Format::R32_UINT,
+            offset: offset_of!(WorldInstance, sampler) as _,
+        },
     ];
 }
 
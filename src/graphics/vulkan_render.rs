@@ -20,18 +20,97 @@ use iron_oxide::{graphics::{self, Buffer, SinlgeTimeCommands, VkBase}, ui::UiSta
 use winit::{dpi::PhysicalSize, raw_window_handle::{HasDisplayHandle, HasWindowHandle}, window::Window};
 
 use super::buffer::create_uniform_buffers;
-use super::UniformBufferObject;
+use super::{Attachment, HdrTarget, InstanceCulling, SamplerPreset, TextureArray, OffscreenTarget, PointLightData, PostProcess, ShadowMap, ShadowSettings, UiUniformBufferObject, UniformBufferObject, MAX_POINT_LIGHTS};
+use super::attachment::supported_samples;
+use super::sampler::create_material_samplers;
+use super::hdr_target::HDR_FORMAT;
 use super::main_pipeline;
-use crate::{game::{app::FPS_LIMIT, Cube, World}, graphics::Vertex};
+use super::offscreen::OFFSCREEN_FORMAT;
+use super::screenshot::{PendingScreenshot, ScreenshotMode};
+use crate::{game::{app::FPS_LIMIT, frustum::Frustum, World}, graphics::{MeshBatch, MeshRegistry, Vertex, WorldInstance}};
+
+pub const MAXFRAMESINFLIGHT: usize = 2;
+/// Requested before clamping to what the device supports.
+pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
+const DEPTH_FORMAT: Format = Format::D24_UNORM_S8_UINT;
+/// Instance buffers never shrink below this many instances.
+const MIN_INSTANCE_CAPACITY: usize = 64;
+/// Vertex and fragment shader of the UI, and of its font rendering.
+pub const UI_SHADERS: (&[u8], &[u8]) = (include_bytes!("../../spv/basic.vert.spv"), include_bytes!("../../spv/basic.frag.spv"));
+pub const FONT_SHADERS: (&[u8], &[u8]) = (include_bytes!("../../spv/bitmap.vert.spv"), include_bytes!("../../spv/bitmap.frag.spv"));
+
+pub enum RenderTarget {
+    Swapchain(super::Swapchain),
+    Offscreen(OffscreenTarget),
+}
+
+impl RenderTarget {
+    pub fn format(&self) -> Format {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.format.format,
+            Self::Offscreen(_) => OFFSCREEN_FORMAT,
+        }
+    }
+
+    pub fn final_layout(&self) -> vk::ImageLayout {
+        match self {
+            Self::Swapchain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            Self::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.image_views.len(),
+            Self::Offscreen(_) => 1,
+        }
+    }
+
+    pub fn framebuffer(&self, image_index: u32) -> vk::Framebuffer {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.framebuffers[image_index as usize],
+            Self::Offscreen(offscreen) => offscreen.framebuffer,
+        }
+    }
+
+    fn create_framebuffers(&mut self, base: &VkBase, render_pass: vk::RenderPass, attachment: vk::ImageView, window_size: PhysicalSize<u32>) {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.create_framebuffer(base, render_pass, attachment, window_size),
+            Self::Offscreen(offscreen) => offscreen.create_framebuffer(base, render_pass, attachment),
+        }
+    }
+
+    fn recreate(&mut self, base: &VkBase, window_size: PhysicalSize<u32>, render_pass: vk::RenderPass, attachment: vk::ImageView) {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.recreate(base, window_size, render_pass, attachment),
+            Self::Offscreen(offscreen) => {
+                offscreen.destroy(&base.device);
+                *offscreen = OffscreenTarget::create(base, window_size);
+                offscreen.create_framebuffer(base, render_pass, attachment);
+            }
+        }
+    }
 
-pub const MAXFRAMESINFLIGHT: usize = 1;
+    fn destroy(&mut self, device: &ash::Device) {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.destroy(device),
+            Self::Offscreen(offscreen) => offscreen.destroy(device),
+        }
+    }
+}
 
 pub struct VulkanRender {
     pub base: iron_oxide::graphics::VkBase,
     
     pub window_size: PhysicalSize<u32>,
-    pub swapchain: super::Swapchain,
+    pub target: RenderTarget,
+    /// Post processing and UI, drawn onto `target`.
     pub render_pass: vk::RenderPass,
+    /// Lit world geometry, drawn into `hdr_target`.
+    pub world_render_pass: vk::RenderPass,
+    pub hdr_target: HdrTarget,
+    pub post_process: PostProcess,
+    pub msaa_samples: vk::SampleCountFlags,
 
     pipeline_layout: vk::PipelineLayout,
     graphics_pipeline: vk::Pipeline,
@@ -39,16 +118,19 @@ pub struct VulkanRender {
     pub command_pool: vk::CommandPool,
     pub single_time_command_pool: vk::CommandPool,
 
-    #[allow(unused)]
-    pub vertex_count: u32,
+    /// Every mesh of `World::meshes` lives in these two, the registry knows where.
     pub vertex_buffer: Buffer,
-
-    pub index_count: u32,
     pub index_buffer: Buffer,
+    /// Meshes of the registry that are already in the buffers, models imported later trigger a new upload.
+    uploaded_meshes: usize,
 
-    pub instance_count: u32,
-    pub instance_buffer: Buffer,
-    pub staging_buffer: Buffer,
+    /// Instances are uploaded every frame, so each frame in flight owns its copy.
+    pub instance_counts: [u32; MAXFRAMESINFLIGHT],
+    mesh_batches: [Vec<MeshBatch>; MAXFRAMESINFLIGHT],
+    pub instance_buffers: [Buffer; MAXFRAMESINFLIGHT],
+    pub instance_staging_buffers: [Buffer; MAXFRAMESINFLIGHT],
+    /// Narrows the instances down to the camera frustum on the GPU, the world pass draws its output.
+    culling: InstanceCulling,
 
     uniform_buffers: [Buffer; MAXFRAMESINFLIGHT],
     uniform_buffers_mapped: [*mut c_void; MAXFRAMESINFLIGHT],
@@ -57,6 +139,7 @@ pub struct VulkanRender {
     ui_uniform_buffers_mapped: [*mut c_void; MAXFRAMESINFLIGHT],
 
     descriptor_pool: vk::DescriptorPool,
+    descriptor_set_layout: vk::DescriptorSetLayout,
     pub descriptor_sets: Vec<vk::DescriptorSet>,
     ui_descriptor_pool: vk::DescriptorPool,
     pub ui_descriptor_sets: Vec<vk::DescriptorSet>,
@@ -69,12 +152,24 @@ pub struct VulkanRender {
     in_flight_fences: [vk::Fence; MAXFRAMESINFLIGHT],
     pub current_frame: usize,
 
-    texture_image: graphics::Image,
+    /// Layers of `World::textures`, new ones are uploaded before the next frame.
+    pub textures: TextureArray,
+    /// Nearest filtering for the UI textures.
     pub texture_sampler: vk::Sampler,
+    /// One per `SamplerPreset`, the world texture array is bound once with each of them.
+    material_samplers: [vk::Sampler; SamplerPreset::COUNT],
 
     font_atlas: graphics::Image,
 
-    pub depth_image: graphics::Image,
+    pub depth_image: Attachment,
+
+    pub shadow_map: ShadowMap,
+    /// Frames whose uniform buffer still holds outdated camera or lighting data.
+    stale_uniform_frames: usize,
+
+    pub screenshot_request: Option<ScreenshotMode>,
+    pending_screenshot: Option<PendingScreenshot>,
+    finished_screenshot: Option<(PhysicalSize<u32>, Vec<u8>)>,
 
     pub ui_state: Rc<RefCell<UiState>>,
     world: *const World,
@@ -89,101 +184,110 @@ impl VulkanRender {
 
         let (base, surface_loader, surface) = VkBase::create(Vec::new(), 0, display_handle, window_handle);
 
+        let window_size = window.inner_size();
+        let swapchain = super::Swapchain::create(&base, window_size, if FPS_LIMIT {vk::PresentModeKHR::FIFO} else {vk::PresentModeKHR::IMMEDIATE}, surface_loader, surface);
+
+        let renderer = Self::create_with_target(base, window_size, RenderTarget::Swapchain(swapchain), world);
+
+        println!("Vulkan time: {:?}", start_time.elapsed());
+
+        renderer
+    }
+
+    /// Creates a renderer without a window that draws the main and UI subpasses into an `OffscreenTarget`.
+    /// Frames are rendered with `render_offscreen`, which returns the RGBA8 pixels of the finished frame.
+    pub fn create_headless(window_size: PhysicalSize<u32>, world: &World) -> Self {
+        let base = VkBase::create_headless(Vec::new(), 0);
+        let target = RenderTarget::Offscreen(OffscreenTarget::create(&base, window_size));
+
+        let renderer = Self::create_with_target(base, window_size, target, world);
+
+        renderer.ui_state.borrow_mut().init_graphics(&renderer.base, renderer.window_size, renderer.render_pass, renderer.ui_descriptor_set_layout, UI_SHADERS, FONT_SHADERS);
+
+        renderer
+    }
+
+    fn create_with_target(base: VkBase, window_size: PhysicalSize<u32>, mut target: RenderTarget, world: &World) -> Self {
         let command_pool = Self::create_command_pool(&base);
         let single_time_command_pool = Self::create_single_time_command_pool(&base);
 
-        let window_size = window.inner_size();
-        let mut swapchain = super::Swapchain::create(&base, window_size, if FPS_LIMIT {vk::PresentModeKHR::FIFO} else {vk::PresentModeKHR::IMMEDIATE}, surface_loader, surface);
-        let render_pass = Self::create_render_pass(&base, swapchain.format, true, true, false, true);
-
-        let (vertices, indices) = Cube::generate_vertices();
-        let instances = world.get_instances();
-        
-        let vertex_count = vertices.len() as u32;
-        let index_count = indices.len() as u32;
-        
-        
-        let (vertex_buffer, index_buffer, instance_buffer) = (
-            Buffer::create(&base, vertices.len() as u64 * size_of::<Vertex>() as u64, vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL),
-            Buffer::create(&base, vertices.len() as u64 * size_of::<u32>() as u64, vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL),
-            Buffer::create(&base, vertices.len() as u64 * size_of::<Matrix4<f32>>() as u64, vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL),
-        );
+        let render_pass = Self::create_render_pass(&base, target.format(), target.final_layout(), true, true, false, true);
+        let msaa_samples = supported_samples(&base, DEFAULT_MSAA_SAMPLES);
+        let world_render_pass = Self::create_world_render_pass(&base, msaa_samples);
 
-        let staging_size = vertex_buffer.size + index_buffer.size + instance_buffer.size;
-        let staging_buffer = Buffer::create(&base, staging_size, vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+        let instance_capacity = instance_capacity(world.cubes.len());
+        let instance_buffers = [(); MAXFRAMESINFLIGHT].map(|_| Self::create_instance_buffer(&base, instance_capacity));
+        let instance_staging_buffers = [(); MAXFRAMESINFLIGHT].map(|_| Self::create_instance_staging_buffer(&base, instance_capacity));
 
-        let mapped_memory = staging_buffer.map_memory(&base.device, staging_size, 0);
-        unsafe {
-            std::ptr::copy_nonoverlapping(vertices.as_ptr(), mapped_memory as _, vertices.len());
-            std::ptr::copy_nonoverlapping(indices.as_ptr(), mapped_memory.byte_add(vertex_buffer.size as _) as _, indices.len());
-            std::ptr::copy_nonoverlapping(instances.as_ptr(), mapped_memory.byte_add(vertex_buffer.size as usize + index_buffer.size as usize) as _, instances.len());
-        };
-        staging_buffer.unmap_memory(&base.device);
+        let culling = InstanceCulling::create(&base, &instance_buffers);
 
         let cmd_buf = SinlgeTimeCommands::begin(&base, single_time_command_pool);
-        staging_buffer.copy(&base, &vertex_buffer, vertex_buffer.size, 0, cmd_buf);
-        staging_buffer.copy(&base, &index_buffer, index_buffer.size, vertex_buffer.size, cmd_buf);
-        staging_buffer.copy(&base, &instance_buffer, instance_buffer.size, vertex_buffer.size + index_buffer.size, cmd_buf);
+        let (vertex_buffer, index_buffer, staging_buffer) = Self::create_mesh_buffers(&base, &world.meshes, cmd_buf);
 
-        let depth_image = Self::create_depth_resources(&base, cmd_buf, Extent3D { width: window_size.width, height: window_size.height, depth: 1 });
-        let (mut texture_image, staging_buf) = Self::create_texture_image(&base, cmd_buf);
-        let (mut font_atlas, staging_buf2) = Self::create_font_atlas(&base, cmd_buf);
+        let (mut font_atlas, staging_buf) = Self::create_font_atlas(&base, cmd_buf);
         SinlgeTimeCommands::end(&base, single_time_command_pool, cmd_buf);
         
+        staging_buffer.destroy(&base.device);
         staging_buf.destroy(&base.device);
-        staging_buf2.destroy(&base.device);
+
+        let mut textures = TextureArray::create(&base, single_time_command_pool);
+        textures.upload(&base, single_time_command_pool, world.textures.sources());
         
-        swapchain.create_framebuffer(&base, render_pass, depth_image.view, window_size);
+        let depth_image = Self::create_depth_resources(&base, window_size, vk::SampleCountFlags::TYPE_1);
+        target.create_framebuffers(&base, render_pass, depth_image.view, window_size);
+        let hdr_target = HdrTarget::create(&base, world_render_pass, Self::create_depth_resources(&base, window_size, msaa_samples), window_size);
         
         
         
         let ui_state = world.ui.clone();
         
         
-        texture_image.create_view(&base, vk::ImageAspectFlags::COLOR);
         font_atlas.create_view(&base, vk::ImageAspectFlags::COLOR);
         
-        let (uniform_buffers, uniform_buffers_mapped) = create_uniform_buffers(&base);
-        let (ui_uniform_buffers, ui_uniform_buffers_mapped) = create_uniform_buffers(&base);
+        let (uniform_buffers, uniform_buffers_mapped) = create_uniform_buffers::<UniformBufferObject>(&base);
+        let (ui_uniform_buffers, ui_uniform_buffers_mapped) = create_uniform_buffers::<UiUniformBufferObject>(&base);
         
         let texture_sampler = Self::create_texture_sampler(&base.device);
+        let material_samplers = create_material_samplers(&base);
         let descriptor_pool = create_descriptor_pool(&base.device);
         let ui_descriptor_pool = create_ui_descriptor_pool(&base.device);
         let descriptor_set_layout = create_descriptor_set_layout(&base.device);
         let ui_descriptor_set_layout = create_ui_descriptor_set_layout(&base.device);
-        let (pipeline_layout, pipeline) = main_pipeline::create_main_pipeline(&base.device, window_size, render_pass, descriptor_set_layout);
-        let descriptor_sets = create_descriptor_sets(&base.device, descriptor_pool, descriptor_set_layout, &uniform_buffers, texture_sampler, texture_image.view, size_of::<UniformBufferObject>() as _);
-        let ui_descriptor_sets = create_ui_descriptor_sets(&base.device, ui_descriptor_pool, ui_descriptor_set_layout, &ui_uniform_buffers, texture_sampler, &[font_atlas.view, texture_image.view], size_of::<UniformBufferObject>() as _);
-        
-        unsafe { base.device.destroy_descriptor_set_layout(descriptor_set_layout, None) };
+        let (pipeline_layout, pipeline) = main_pipeline::create_main_pipeline(&base.device, window_size, world_render_pass, descriptor_set_layout, msaa_samples);
+        let post_process = PostProcess::create(&base, single_time_command_pool, window_size, render_pass, hdr_target.color_image.view);
+        let shadow_map = ShadowMap::create(&base, ShadowSettings::default(), descriptor_set_layout);
+        let descriptor_sets = create_descriptor_sets(&base.device, descriptor_pool, descriptor_set_layout, &uniform_buffers, &material_samplers, textures.view, &shadow_map, size_of::<UniformBufferObject>() as _);
+        let ui_descriptor_sets = create_ui_descriptor_sets(&base.device, ui_descriptor_pool, ui_descriptor_set_layout, &ui_uniform_buffers, texture_sampler, &[font_atlas.view, textures.default_view], size_of::<UiUniformBufferObject>() as _);
         
         let command_buffers = Self::create_command_buffers(&base.device, command_pool);
-        let (image_available_semaphores, render_finsih_semaphores, in_flight_fences)= Self::create_sync_object(&base.device, swapchain.image_views.len());
+        let (image_available_semaphores, render_finsih_semaphores, in_flight_fences)= Self::create_sync_object(&base.device, target.image_count());
         
         let world = world as *const World;
         
-        println!("Vulkan time: {:?}", start_time.elapsed());
-        
         let mut renderer = Self {
             window_size,
             base,
-            swapchain,
+            target,
             pipeline_layout,
             render_pass,
+            world_render_pass,
+            hdr_target,
+            post_process,
+            msaa_samples,
             graphics_pipeline: pipeline,
 
             command_pool,
             single_time_command_pool,
     
-            vertex_count,
             vertex_buffer,
-
-            index_count,
             index_buffer,
+            uploaded_meshes: world.meshes.mesh_count(),
 
-            instance_count: instances.len() as _,
-            instance_buffer,
-            staging_buffer,
+            instance_counts: [0; MAXFRAMESINFLIGHT],
+            mesh_batches: [const { Vec::new() }; MAXFRAMESINFLIGHT],
+            instance_buffers,
+            instance_staging_buffers,
+            culling,
     
             uniform_buffers,
             uniform_buffers_mapped,
@@ -192,6 +296,7 @@ impl VulkanRender {
     
             descriptor_pool,
             ui_descriptor_pool,
+            descriptor_set_layout,
             descriptor_sets,
             ui_descriptor_sets,
             ui_descriptor_set_layout,
@@ -202,13 +307,21 @@ impl VulkanRender {
             in_flight_fences,
     
             current_frame: 0,
-            texture_image,
+            textures,
     
             font_atlas,
     
             texture_sampler,
+            material_samplers,
             depth_image,
 
+            shadow_map,
+            stale_uniform_frames: MAXFRAMESINFLIGHT,
+
+            screenshot_request: None,
+            pending_screenshot: None,
+            finished_screenshot: None,
+
             ui_state,
             world,
         };
@@ -228,40 +341,78 @@ impl VulkanRender {
 
         unsafe { self.base.device.device_wait_idle().unwrap_unchecked() };
         self.depth_image.destroy(&self.base.device);
+        self.hdr_target.destroy(&self.base.device);
 
-        let cmd_buf = SinlgeTimeCommands::begin(&self.base, self.single_time_command_pool);
-        self.depth_image = Self::create_depth_resources(&self.base, cmd_buf, Extent3D { width: self.window_size.width, height: self.window_size.height, depth: 1 });
-        SinlgeTimeCommands::submit(&self.base, cmd_buf);
-
-        self.swapchain.recreate(&self.base, new_size, self.render_pass, self.depth_image.view);
+        self.depth_image = Self::create_depth_resources(&self.base, new_size, vk::SampleCountFlags::TYPE_1);
+        self.target.recreate(&self.base, new_size, self.render_pass, self.depth_image.view);
+        self.hdr_target = HdrTarget::create(&self.base, self.world_render_pass, Self::create_depth_resources(&self.base, new_size, self.msaa_samples), new_size);
+        self.post_process.resize(&self.base, new_size, self.hdr_target.color_image.view);
         self.update_ui_uniform_buffer();
 
         self.ui_state.borrow_mut().resize(new_size.into());
+    }
+
+    /// Switches the world pass to `samples` (1, 2, 4 or 8), clamped to what the device supports.
+    /// Rebuilds the world render pass, its targets and the main pipeline.
+    pub fn set_msaa(&mut self, samples: u32) -> vk::SampleCountFlags {
+        let samples = supported_samples(&self.base, samples);
+        if samples == self.msaa_samples {
+            return samples;
+        }
+
+        let device = &self.base.device;
+        unsafe {
+            device.device_wait_idle().unwrap();
+            device.destroy_pipeline(self.graphics_pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_render_pass(self.world_render_pass, None);
+        }
+        self.hdr_target.destroy(device);
+
+        self.msaa_samples = samples;
+        self.world_render_pass = Self::create_world_render_pass(&self.base, samples);
+        self.hdr_target = HdrTarget::create(&self.base, self.world_render_pass, Self::create_depth_resources(&self.base, self.window_size, samples), self.window_size);
+        (self.pipeline_layout, self.graphics_pipeline) = main_pipeline::create_main_pipeline(&self.base.device, self.window_size, self.world_render_pass, self.descriptor_set_layout, samples);
+        self.post_process.set_input(&self.base.device, self.hdr_target.color_image.view);
+
+        samples
+    }
+
+    /// Rebuilds the shadow map, its pipeline and the descriptors sampling it with new settings.
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) {
+        if settings == self.shadow_map.settings {
+            return;
+        }
 
-        SinlgeTimeCommands::end_after_submit(&self.base, self.single_time_command_pool, cmd_buf);
+        unsafe { self.base.device.device_wait_idle().unwrap() };
+        self.shadow_map.destroy(&self.base.device);
+        self.shadow_map = ShadowMap::create(&self.base, settings, self.descriptor_set_layout);
+        write_shadow_descriptors(&self.base.device, &self.descriptor_sets, &self.shadow_map);
+        self.stale_uniform_frames = MAXFRAMESINFLIGHT;
     }
 
-    fn create_render_pass(base: &VkBase, format: vk::SurfaceFormatKHR, clear: bool, depth: bool, has_previus: bool, is_final: bool) -> vk::RenderPass {
+    fn create_render_pass(base: &VkBase, format: Format, final_layout: vk::ImageLayout, clear: bool, depth: bool, has_previus: bool, is_final: bool) -> vk::RenderPass {
         let color_attachment = vk::AttachmentDescription {
-            format: format.format,
+            format,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: if clear {vk::AttachmentLoadOp::CLEAR} else { vk::AttachmentLoadOp::DONT_CARE },
             store_op: if is_final {vk::AttachmentStoreOp::STORE} else {vk::AttachmentStoreOp::DONT_CARE},
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: if has_previus {vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL} else { vk::ImageLayout::UNDEFINED },
-            final_layout: if is_final {vk::ImageLayout::PRESENT_SRC_KHR} else { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL },
+            final_layout: if is_final { final_layout } else { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL },
             flags: vk::AttachmentDescriptionFlags::empty()
         };
 
+        // Cleared on load, so the previous content and layout do not matter
         let depth_attachment = vk::AttachmentDescription {
-            format: Format::D24_UNORM_S8_UINT,
+            format: DEPTH_FORMAT,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::CLEAR,
             store_op: vk::AttachmentStoreOp::DONT_CARE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-            initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            initial_layout: vk::ImageLayout::UNDEFINED,
             final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
             flags: vk::AttachmentDescriptionFlags::empty(),
         };
@@ -334,9 +485,116 @@ impl VulkanRender {
 
     }
 
+    /// With more than one sample the world is drawn into a multisampled color attachment
+    /// that is resolved into the sampled HDR image, see `HdrTarget` for the attachment order.
+    fn create_world_render_pass(base: &VkBase, samples: vk::SampleCountFlags) -> vk::RenderPass {
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+
+        let color_attachment = vk::AttachmentDescription {
+            format: HDR_FORMAT,
+            samples,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: if multisampled { vk::AttachmentStoreOp::DONT_CARE } else { vk::AttachmentStoreOp::STORE },
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: if multisampled { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL } else { vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL },
+            flags: vk::AttachmentDescriptionFlags::empty()
+        };
+
+        let depth_attachment = vk::AttachmentDescription {
+            format: DEPTH_FORMAT,
+            samples,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            flags: vk::AttachmentDescriptionFlags::empty(),
+        };
+
+        let resolve_attachment = vk::AttachmentDescription {
+            format: HDR_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: vk::AttachmentDescriptionFlags::empty()
+        };
+
+        let color_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        };
+
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let resolve_attachment_ref = vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        };
+
+        let attachments = if multisampled {
+            vec![color_attachment, depth_attachment, resolve_attachment]
+        } else {
+            vec![color_attachment, depth_attachment]
+        };
+
+        let subpass = vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            p_resolve_attachments: if multisampled { &resolve_attachment_ref } else { null() },
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            ..Default::default()
+        };
+
+        let dependencies = [
+            // The post pass of the previous frame must be done reading the image
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PipelineStageFlags::FRAGMENT_SHADER | PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: AccessFlags::SHADER_READ | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            }
+        ];
+
+        let render_pass_info = vk::RenderPassCreateInfo {
+            attachment_count: attachments.len() as _,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            dependency_count: dependencies.len() as _,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe { base.device.create_render_pass(&render_pass_info, None).unwrap() }
+    }
+
     fn create_command_pool(base: &VkBase) -> vk::CommandPool {
+        // Command buffers are reset one by one, resetting the whole pool would hit frames still in flight
         let pool_info = vk::CommandPoolCreateInfo {
-            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            flags: vk::CommandPoolCreateFlags::TRANSIENT | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
             queue_family_index: base.queue_family_index,
             ..Default::default()
         };
@@ -380,12 +638,11 @@ impl VulkanRender {
 
         unsafe {
             self.base.device.wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX).unwrap();
-            self.base.device.reset_fences(&[self.in_flight_fences[self.current_frame]]).unwrap();
-            self.base.device.reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty()).unwrap();
         };
 
         let image_index = unsafe { 
-            match self.swapchain.loader.acquire_next_image(self.swapchain.inner, u64::MAX, self.image_available_semaphores[self.current_frame], vk::Fence::null()) {
+            let RenderTarget::Swapchain(swapchain) = &self.target else { return };
+            match swapchain.loader.acquire_next_image(swapchain.inner, u64::MAX, self.image_available_semaphores[self.current_frame], vk::Fence::null()) {
                 Ok(result) => {
                     if result.1 {
                         return;
@@ -397,9 +654,20 @@ impl VulkanRender {
         };
 
 
+        // Only reset once an image was acquired, an early return above must leave the fence signaled
+        unsafe {
+            self.base.device.reset_fences(&[self.in_flight_fences[self.current_frame]]).unwrap();
+            self.base.device.reset_command_buffer(self.command_buffers[self.current_frame], vk::CommandBufferResetFlags::empty()).unwrap();
+        };
+
+        if let (Some(mode), RenderTarget::Swapchain(swapchain)) = (self.screenshot_request.take(), &self.target) {
+            self.pending_screenshot = Some(PendingScreenshot::create(&self.base, mode, swapchain.images[image_index as usize], self.window_size));
+        }
+
         self.update_ui();
+        self.upload_instances();
 
-        self.record_command_buffer(image_index);
+        self.record_command_buffer(self.target.framebuffer(image_index));
         self.update_uniform_buffer();
 
         let submit_info = vk::SubmitInfo {
@@ -417,38 +685,241 @@ impl VulkanRender {
             return;
         }
 
+        if let Some(pending) = self.pending_screenshot.take() {
+            unsafe { self.base.device.wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX).unwrap() };
+            self.finished_screenshot = Some(pending.finish(&self.base.device));
+        }
+
+        let RenderTarget::Swapchain(swapchain) = &self.target else { return };
+
         let present_info = vk::PresentInfoKHR {
             wait_semaphore_count: 1,
             p_wait_semaphores: &self.render_finsih_semaphores[image_index as usize],
             swapchain_count: 1,
-            p_swapchains: &self.swapchain.inner,
+            p_swapchains: &swapchain.inner,
             p_image_indices: &image_index,
             ..Default::default()
         };
 
-        if unsafe { self.swapchain.loader.queue_present(self.base.queue, &present_info).is_err() } {
+        if unsafe { swapchain.loader.queue_present(self.base.queue, &present_info).is_err() } {
             return;
         }
 
         self.current_frame = (self.current_frame + 1) % MAXFRAMESINFLIGHT;
     }
 
-    fn record_command_buffer(&mut self, index: u32) {
+    /// Returns the pixels of the last frame captured through `screenshot_request`, once it has been drawn.
+    pub fn take_screenshot(&mut self) -> Option<(PhysicalSize<u32>, Vec<u8>)> {
+        self.finished_screenshot.take()
+    }
+
+    /// Renders one frame into the offscreen target and blocks until its pixels are read back.
+    pub fn render_offscreen(&mut self) -> Vec<u8> {
+        unsafe {
+            self.base.device.wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX).unwrap();
+            self.base.device.reset_fences(&[self.in_flight_fences[self.current_frame]]).unwrap();
+            self.base.device.reset_command_buffer(self.command_buffers[self.current_frame], vk::CommandBufferResetFlags::empty()).unwrap();
+        };
+
+        self.update_ui();
+        self.upload_instances();
+
+        self.record_command_buffer(self.target.framebuffer(0));
+        self.update_uniform_buffer();
+
+        let submit_info = vk::SubmitInfo {
+            command_buffer_count: 1,
+            p_command_buffers: &self.command_buffers[self.current_frame],
+            ..Default::default()
+        };
+
+        self.base.queue_submit(&[submit_info], self.in_flight_fences[self.current_frame]).unwrap();
+        unsafe { self.base.device.wait_for_fences(&[self.in_flight_fences[self.current_frame]], true, u64::MAX).unwrap() };
+
+        let RenderTarget::Offscreen(offscreen) = &self.target else { panic!("render_offscreen requires a headless renderer") };
+        offscreen.read_pixels(&self.base, self.single_time_command_pool)
+    }
+
+    fn create_instance_buffer(base: &VkBase, capacity: usize) -> Buffer {
+        Buffer::create(base, (capacity * size_of::<WorldInstance>()) as u64, vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+    }
+
+    fn create_instance_staging_buffer(base: &VkBase, capacity: usize) -> Buffer {
+        Buffer::create(base, (capacity * size_of::<WorldInstance>()) as u64, vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    /// Both buffers are filled from one staging buffer, which the caller destroys once `cmd_buf` has run.
+    fn create_mesh_buffers(base: &VkBase, meshes: &MeshRegistry, cmd_buf: vk::CommandBuffer) -> (Buffer, Buffer, Buffer) {
+        let (vertices, indices) = (&meshes.vertices, &meshes.indices);
+
+        let (vertex_buffer, index_buffer) = (
+            Buffer::create(base, vertices.len() as u64 * size_of::<Vertex>() as u64, vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+            Buffer::create(base, indices.len() as u64 * size_of::<u32>() as u64, vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+        );
+
+        let staging_size = vertex_buffer.size + index_buffer.size;
+        let staging_buffer = Buffer::create(base, staging_size, vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+
+        let mapped_memory = staging_buffer.map_memory(&base.device, staging_size, 0);
+        unsafe {
+            std::ptr::copy_nonoverlapping(vertices.as_ptr(), mapped_memory as _, vertices.len());
+            std::ptr::copy_nonoverlapping(indices.as_ptr(), mapped_memory.byte_add(vertex_buffer.size as _) as _, indices.len());
+        };
+        staging_buffer.unmap_memory(&base.device);
+
+        staging_buffer.copy(base, &vertex_buffer, vertex_buffer.size, 0, cmd_buf);
+        staging_buffer.copy(base, &index_buffer, index_buffer.size, vertex_buffer.size, cmd_buf);
+
+        (vertex_buffer, index_buffer, staging_buffer)
+    }
+
+    /// Replaces the mesh buffers after meshes were added to the world's registry.
+    fn upload_meshes(&mut self) {
+        let world = unsafe { &*self.world };
+
+        // The other frame in flight may still read the old buffers
+        unsafe { self.base.device.device_wait_idle().unwrap() };
+        self.vertex_buffer.destroy(&self.base.device);
+        self.index_buffer.destroy(&self.base.device);
+
+        let cmd_buf = SinlgeTimeCommands::begin(&self.base, self.single_time_command_pool);
+        let (vertex_buffer, index_buffer, staging_buffer) = Self::create_mesh_buffers(&self.base, &world.meshes, cmd_buf);
+        SinlgeTimeCommands::end(&self.base, self.single_time_command_pool, cmd_buf);
+        staging_buffer.destroy(&self.base.device);
+
+        self.vertex_buffer = vertex_buffer;
+        self.index_buffer = index_buffer;
+        self.uploaded_meshes = world.meshes.mesh_count();
+    }
+
+    /// Writes the current instances into this frame's staging buffer.
+    /// The copy to the device local buffer is recorded at the start of the frame's command buffer.
+    fn upload_instances(&mut self) {
+        let world = unsafe { &*self.world };
+        if world.meshes.mesh_count() != self.uploaded_meshes {
+            self.upload_meshes();
+        }
+        if world.textures.sources().len() != self.textures.uploaded() {
+            self.textures.upload(&self.base, self.single_time_command_pool, world.textures.sources());
+        }
+
+        // Shadow casters outside the view can still throw a shadow into it, so the sun's frustum counts as well.
+        // The world pass only needs the camera's part, the cull shader sorts out the rest per frame.
+        let camera = &world.camera;
+        let view_proj = camera.projection(self.window_size.width as f32 / self.window_size.height as f32) * camera.view_matrix();
+        let light_view_proj = self.shadow_map.settings.light_view_proj(world.lighting.sun_direction, camera.position);
+        let camera_frustum = Frustum::from_view_proj(view_proj);
+        let (instances, batches) = world.get_instances(&[camera_frustum, Frustum::from_view_proj(light_view_proj)]);
+        let frame = self.current_frame;
+
+        // The fence of this frame was waited on, so its buffers are no longer in use and can be replaced
+        let capacity = self.instance_staging_buffers[frame].size as usize / size_of::<WorldInstance>();
+        let wanted = instance_capacity(instances.len());
+        if instances.len() > capacity || wanted < capacity / 4 {
+            self.instance_buffers[frame].destroy(&self.base.device);
+            self.instance_staging_buffers[frame].destroy(&self.base.device);
+            self.instance_buffers[frame] = Self::create_instance_buffer(&self.base, wanted);
+            self.instance_staging_buffers[frame] = Self::create_instance_staging_buffer(&self.base, wanted);
+        }
+
+        self.culling.prepare(&self.base, frame, &self.instance_buffers[frame], instances.len() as _, &batches, &world.meshes, &camera_frustum);
+        self.mesh_batches[frame] = batches;
+
+        let count = instances.len();
+        let buffer_size = (count * size_of::<WorldInstance>()) as u64;
+        self.instance_counts[frame] = count as _;
+        if count == 0 {
+            return;
+        }
+
+        let staging_buffer = &self.instance_staging_buffers[frame];
+
+        let mapped_memory = staging_buffer.map_memory(&self.base.device, buffer_size, 0);
+        unsafe { ptr::copy_nonoverlapping(instances.as_ptr(), mapped_memory as _, count) };
+        staging_buffer.unmap_memory(&self.base.device);
+    }
+
+    fn record_instance_copy(&self, cmd_buf: vk::CommandBuffer) {
+        let frame = self.current_frame;
+        let buffer_size = self.instance_counts[frame] as u64 * size_of::<WorldInstance>() as u64;
+        if buffer_size == 0 {
+            return;
+        }
+
+        let region = vk::BufferCopy { src_offset: 0, dst_offset: 0, size: buffer_size };
+
+        let barrier = vk::BufferMemoryBarrier {
+            src_access_mask: AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: AccessFlags::VERTEX_ATTRIBUTE_READ | AccessFlags::SHADER_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.instance_buffers[frame].inner,
+            offset: 0,
+            size: buffer_size,
+            ..Default::default()
+        };
+
+        unsafe {
+            self.base.device.cmd_copy_buffer(cmd_buf, self.instance_staging_buffers[frame].inner, self.instance_buffers[frame].inner, &[region]);
+            self.base.device.cmd_pipeline_barrier(cmd_buf, PipelineStageFlags::TRANSFER, PipelineStageFlags::VERTEX_INPUT | PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[barrier], &[]);
+        }
+    }
+
+    /// Binds the shared mesh buffers and draws every batch of the current frame with the bound pipeline.
+    /// Draws all uploaded instances, the shadow pass uses it since the camera culling does not apply to the sun.
+    fn record_draws(&self, cmd_buf: vk::CommandBuffer) {
+        let device = &self.base.device;
+        let world = unsafe { &*self.world };
+        let frame = self.current_frame;
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(cmd_buf, 0, &[self.vertex_buffer.inner, self.instance_buffers[frame].inner], &[0, 0]);
+            device.cmd_bind_index_buffer(cmd_buf, self.index_buffer.inner, 0, vk::IndexType::UINT32);
+
+            for batch in &self.mesh_batches[frame] {
+                let mesh = world.meshes.get(batch.mesh);
+                device.cmd_draw_indexed(cmd_buf, mesh.index_count, batch.instance_count, mesh.first_index, mesh.vertex_offset, batch.first_instance);
+            }
+        }
+    }
+
+    /// Draws the output of the cull shader with indirect draws.
+    fn record_culled_draws(&self, cmd_buf: vk::CommandBuffer) {
+        let device = &self.base.device;
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(cmd_buf, 0, &[self.vertex_buffer.inner], &[0]);
+            device.cmd_bind_index_buffer(cmd_buf, self.index_buffer.inner, 0, vk::IndexType::UINT32);
+        }
+        self.culling.record_draws(device, cmd_buf, self.current_frame);
+    }
+
+    fn record_command_buffer(&mut self, framebuffer: vk::Framebuffer) {
         let clear_values = [
             vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] } },
             vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
         ];
 
-        let render_pass_info = vk::RenderPassBeginInfo {
-            render_pass: self.render_pass,
-            framebuffer: self.swapchain.framebuffers[index as usize],
-            render_area: vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 },
-            extent: vk::Extent2D { width: self.window_size.width, height: self.window_size.height }},
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: vk::Extent2D { width: self.window_size.width, height: self.window_size.height },
+        };
+
+        let world_pass_info = vk::RenderPassBeginInfo {
+            render_pass: self.world_render_pass,
+            framebuffer: self.hdr_target.framebuffer,
+            render_area,
             clear_value_count: clear_values.len() as _,
             p_clear_values: clear_values.as_ptr(),
             ..Default::default()
         };
 
+        let render_pass_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer,
+            ..world_pass_info
+        };
+
         let view_port = vk::Viewport {
             x: 0.0,
             y: 0.0,
@@ -458,12 +929,10 @@ impl VulkanRender {
             max_depth: 1.0
         };
         
-        let scissor = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: vk::Extent2D { width: self.window_size.width, height: self.window_size.height },
-        };
+        let scissor = render_area;
 
         let device = &self.base.device;
+        let cmd_buf = self.command_buffers[self.current_frame];
         
         let begin_info = vk::CommandBufferBeginInfo {
             flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
@@ -471,23 +940,37 @@ impl VulkanRender {
         };
         
         unsafe {
-            device.begin_command_buffer(self.command_buffers[self.current_frame], &begin_info).unwrap();
+            device.begin_command_buffer(cmd_buf, &begin_info).unwrap();
+            self.record_instance_copy(cmd_buf);
+            self.culling.record(device, cmd_buf, self.current_frame);
+
+            self.shadow_map.record(device, cmd_buf, self.descriptor_sets[self.current_frame], |cmd_buf| self.record_draws(cmd_buf));
             
-            device.cmd_set_scissor(self.command_buffers[self.current_frame], 0, &[scissor]);
-            device.cmd_set_viewport(self.command_buffers[self.current_frame], 0, &[view_port]);
+            device.cmd_set_scissor(cmd_buf, 0, &[scissor]);
+            device.cmd_set_viewport(cmd_buf, 0, &[view_port]);
             
-            device.cmd_begin_render_pass(self.command_buffers[self.current_frame], &render_pass_info, vk::SubpassContents::INLINE);
-            device.cmd_bind_pipeline(self.command_buffers[self.current_frame], vk::PipelineBindPoint::GRAPHICS, self.graphics_pipeline);
-            device.cmd_bind_vertex_buffers(self.command_buffers[self.current_frame], 0, &[self.vertex_buffer.inner, self.instance_buffer.inner], &[0, 0]);
-            device.cmd_bind_index_buffer(self.command_buffers[self.current_frame], self.index_buffer.inner, 0, vk::IndexType::UINT32);
-            device.cmd_bind_descriptor_sets(self.command_buffers[self.current_frame], vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[self.descriptor_sets[self.current_frame]], &[]);
-            device.cmd_draw_indexed(self.command_buffers[self.current_frame], self.index_count, self.instance_count, 0, 0, 0);
-            device.cmd_next_subpass(self.command_buffers[self.current_frame], vk::SubpassContents::INLINE);
-
-            self.ui_state.borrow().draw(&self.base.device, self.command_buffers[self.current_frame], self.ui_descriptor_sets[self.current_frame]);
-            device.cmd_end_render_pass(self.command_buffers[self.current_frame]);
+            device.cmd_begin_render_pass(cmd_buf, &world_pass_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.graphics_pipeline);
+            device.cmd_bind_descriptor_sets(cmd_buf, vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[self.descriptor_sets[self.current_frame]], &[]);
+            self.record_culled_draws(cmd_buf);
+            device.cmd_end_render_pass(cmd_buf);
+
+            self.post_process.record_effects(device, cmd_buf, self.window_size);
+
+            device.cmd_begin_render_pass(cmd_buf, &render_pass_info, vk::SubpassContents::INLINE);
+            self.post_process.record(device, cmd_buf);
+            device.cmd_next_subpass(cmd_buf, vk::SubpassContents::INLINE);
+
+            if self.pending_screenshot.as_ref().is_none_or(|pending| pending.mode == ScreenshotMode::WithUi) {
+                self.ui_state.borrow().draw(&self.base.device, cmd_buf, self.ui_descriptor_sets[self.current_frame]);
+            }
+            device.cmd_end_render_pass(cmd_buf);
+
+            if let Some(pending) = &self.pending_screenshot {
+                pending.record_copy(device, cmd_buf);
+            }
             
-            device.end_command_buffer(self.command_buffers[self.current_frame]).unwrap();
+            device.end_command_buffer(cmd_buf).unwrap();
         };
     }
 
@@ -521,18 +1004,41 @@ impl VulkanRender {
     fn update_uniform_buffer(&mut self) {
         let world = unsafe { &mut *(self.world as *mut World) }; 
 
-        if !world.camera.moved {
+        if world.camera.moved || world.lighting.changed {
+            self.stale_uniform_frames = MAXFRAMESINFLIGHT;
+        }
+        if self.stale_uniform_frames == 0 {
             return;
         }
+        world.lighting.changed = false;
+        self.stale_uniform_frames -= 1;
 
         let view = world.camera.view();
         let proj = world.camera.projection(self.window_size.width as f32 / self.window_size.height as f32);
 
-        let ubo = proj * view;
-
-        for uniform_buffer in self.uniform_buffers_mapped {
-            unsafe { ptr::copy_nonoverlapping(&ubo as _, uniform_buffer as _, 1) };
+        let lighting = &world.lighting;
+        let mut point_lights = [PointLightData::default(); MAX_POINT_LIGHTS];
+        for (data, light) in point_lights.iter_mut().zip(&lighting.point_lights) {
+            data.position = [light.position.x, light.position.y, light.position.z, light.radius];
+            data.color = [light.color.x * light.intensity, light.color.y * light.intensity, light.color.z * light.intensity, 0.0];
         }
+
+        let camera_position = world.camera.position;
+        let shadow_settings = &self.shadow_map.settings;
+        let ubo = UniformBufferObject {
+            view_proj: proj * view,
+            camera_position: [camera_position.x, camera_position.y, camera_position.z, 1.0],
+            sun_direction: [lighting.sun_direction.x, lighting.sun_direction.y, lighting.sun_direction.z, 0.0],
+            sun_color: [lighting.sun_color.x * lighting.sun_intensity, lighting.sun_color.y * lighting.sun_intensity, lighting.sun_color.z * lighting.sun_intensity, 0.0],
+            ambient_color: [lighting.ambient_color.x * lighting.ambient_intensity, lighting.ambient_color.y * lighting.ambient_intensity, lighting.ambient_color.z * lighting.ambient_intensity, 0.0],
+            point_lights,
+            point_light_count: [lighting.point_lights.len().min(MAX_POINT_LIGHTS) as u32, 0, 0, 0],
+            light_view_proj: shadow_settings.light_view_proj(lighting.sun_direction, camera_position),
+            shadow_params: [1.0 / shadow_settings.resolution as f32, shadow_settings.normal_bias, 0.0, 0.0],
+        };
+
+        // The other frames may still be in flight, they catch up through `stale_uniform_frames`
+        unsafe { ptr::copy_nonoverlapping(&ubo as _, self.uniform_buffers_mapped[self.current_frame] as _, 1) };
     }
 
     fn update_ui_uniform_buffer(&mut self) {
@@ -543,34 +1049,6 @@ impl VulkanRender {
         }
     }
 
-    fn create_texture_image(base: &VkBase, cmd_buf: vk::CommandBuffer) -> (graphics::Image, Buffer) {
-        let decoder = png::Decoder::new(&include_bytes!("../../textures/texture.png")[..]);
-
-        let mut reader = decoder.read_info().unwrap();
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let info = reader.next_frame(&mut buf).unwrap();
-        let width = info.width;
-        let height = info.height;
-        let image_size = buf.len() as u64;
-        let extent = Extent3D { width, height, depth: 1 };
-        
-        let staging_buffer = Buffer::create(base, image_size, vk::BufferUsageFlags::TRANSFER_SRC, MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT);
-
-        let mapped_memory = staging_buffer.map_memory(&base.device, image_size, 0);
-        unsafe { 
-            ptr::copy_nonoverlapping(buf.as_ptr(), mapped_memory as _, image_size as usize);
-        };
-        staging_buffer.unmap_memory(&base.device);
-
-        let mut texture_image = graphics::Image::create(base, extent, Format::R8G8B8A8_SRGB, vk::ImageTiling::OPTIMAL, ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED, MemoryPropertyFlags::DEVICE_LOCAL);
-
-        texture_image.trasition_layout(base, cmd_buf, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
-        texture_image.copy_from_buffer(base, cmd_buf, &staging_buffer, extent, vk::ImageAspectFlags::COLOR);
-        texture_image.trasition_layout(base, cmd_buf, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
-
-        (texture_image, staging_buffer)
-    }
-
     fn create_font_atlas(base: &VkBase, cmd_buf: vk::CommandBuffer) -> (graphics::Image, Buffer) {
         let decoder = png::Decoder::new(&include_bytes!("../../font/default8.png")[..]);
 
@@ -622,25 +1100,33 @@ impl VulkanRender {
         unsafe { device.create_sampler(&create_info, None).unwrap() }
     }
 
-    fn create_depth_resources(base: &VkBase, cmd_buf: vk::CommandBuffer, extent: Extent3D) -> graphics::Image {
-        let mut depth_image = graphics::Image::create(base, extent, Format::D24_UNORM_S8_UINT, vk::ImageTiling::OPTIMAL, ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, MemoryPropertyFlags::DEVICE_LOCAL);
-        depth_image.trasition_layout(base, cmd_buf, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
-        depth_image.create_view(base, vk::ImageAspectFlags::DEPTH);
-        depth_image
+    fn create_depth_resources(base: &VkBase, size: PhysicalSize<u32>, samples: vk::SampleCountFlags) -> Attachment {
+        let extent = vk::Extent2D { width: size.width, height: size.height };
+        Attachment::create(base, extent, DEPTH_FORMAT, ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, samples, vk::ImageAspectFlags::DEPTH)
     }
 
     pub fn update_ui(&mut self) {
         self.ui_state.borrow_mut().update(&self.base, self.single_time_command_pool);
     }
 
+    /// Swaps in a freshly built UI, used whenever the sidebar content changes.
+    pub fn replace_ui(&mut self, mut ui: UiState) {
+        // The frames in flight may still draw the old UI buffers
+        unsafe { self.base.device.device_wait_idle().unwrap() };
+
+        ui.visible = self.ui_state.borrow().visible;
+        ui.init_graphics(&self.base, self.window_size, self.render_pass, self.ui_descriptor_set_layout, UI_SHADERS, FONT_SHADERS);
+        self.ui_state.replace(ui).destroy(&self.base.device);
+    }
+
     pub fn destroy(&mut self) {
         unsafe {
             let device = &self.base.device;
             #[cfg(debug_assertions)]
             self.base.debug_utils.destroy_debug_utils_messenger(self.base.utils_messenger, None);
 
-            for i in 0..self.swapchain.image_views.len() {
-                device.destroy_semaphore(self.render_finsih_semaphores[i], None);
+            for semaphore in &self.render_finsih_semaphores {
+                device.destroy_semaphore(*semaphore, None);
             }
 
             for i in 0..MAXFRAMESINFLIGHT {
@@ -651,6 +1137,7 @@ impl VulkanRender {
             }
 
             self.ui_state.borrow().destroy(device);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
             device.destroy_descriptor_set_layout(self.ui_descriptor_set_layout, None);
             device.destroy_command_pool(self.command_pool, None);
             device.destroy_command_pool(self.single_time_command_pool, None);
@@ -659,15 +1146,25 @@ impl VulkanRender {
             device.destroy_descriptor_pool(self.descriptor_pool, None);
             device.destroy_descriptor_pool(self.ui_descriptor_pool, None);
             device.destroy_render_pass(self.render_pass, None);
-            self.swapchain.destroy(device);
+            device.destroy_render_pass(self.world_render_pass, None);
+            self.post_process.destroy(device);
+            self.hdr_target.destroy(device);
+            self.target.destroy(device);
             device.destroy_sampler(self.texture_sampler, None);
+            for sampler in self.material_samplers {
+                device.destroy_sampler(sampler, None);
+            }
             self.depth_image.destroy(device);
-            self.texture_image.destroy(device);
+            self.shadow_map.destroy(device);
+            self.textures.destroy(device);
             self.font_atlas.destroy(device);
             self.vertex_buffer.destroy(device);
             self.index_buffer.destroy(device);
-            self.instance_buffer.destroy(device);
-            self.staging_buffer.destroy(device);
+            for i in 0..MAXFRAMESINFLIGHT {
+                self.instance_buffers[i].destroy(device);
+                self.instance_staging_buffers[i].destroy(device);
+            }
+            self.culling.destroy(device);
             device.destroy_device(None);
             self.base.instance.destroy_instance(None);
         };
@@ -675,18 +1172,32 @@ impl VulkanRender {
 
 }
 
+/// Room for `count` instances with headroom, so adding a few cubes does not reallocate every time.
+fn instance_capacity(count: usize) -> usize {
+    count.next_power_of_two().max(MIN_INSTANCE_CAPACITY)
+}
+
 fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
     let ubo_layout_binding = vk::DescriptorSetLayoutBinding {
         binding: 0,
         descriptor_count: 1,
         descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-        stage_flags: ShaderStageFlags::VERTEX,
+        stage_flags: ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
         p_immutable_samplers: null(),
         _marker: std::marker::PhantomData,
     };
 
     let sampler_layout_binding = vk::DescriptorSetLayoutBinding {
         binding: 1,
+        descriptor_count: SamplerPreset::COUNT as _,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        stage_flags: ShaderStageFlags::FRAGMENT,
+        p_immutable_samplers: null(),
+        _marker: std::marker::PhantomData,
+    };
+
+    let shadow_layout_binding = vk::DescriptorSetLayoutBinding {
+        binding: 2,
         descriptor_count: 1,
         descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
         stage_flags: ShaderStageFlags::FRAGMENT,
@@ -694,7 +1205,7 @@ fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout
         _marker: std::marker::PhantomData,
     };
 
-    let bindings = [ubo_layout_binding, sampler_layout_binding];
+    let bindings = [ubo_layout_binding, sampler_layout_binding, shadow_layout_binding];
 
     let layout_info = vk::DescriptorSetLayoutCreateInfo {
         binding_count: bindings.len() as _,
@@ -741,7 +1252,7 @@ fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
         },
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: MAXFRAMESINFLIGHT as _,
+            descriptor_count: MAXFRAMESINFLIGHT as u32 * (SamplerPreset::COUNT as u32 + 1),
         }
     ];
 
@@ -782,8 +1293,9 @@ fn create_descriptor_sets(
     descriptor_pool: vk::DescriptorPool,
     descriptor_set_layout: vk::DescriptorSetLayout,
     uniform_buffers: &[Buffer],
-    textures_sampler: vk::Sampler,
+    material_samplers: &[vk::Sampler; SamplerPreset::COUNT],
     texture_image_view: vk::ImageView,
+    shadow_map: &ShadowMap,
     ubo_size: u64,
 ) -> Vec<vk::DescriptorSet> {
     let layouts: [vk::DescriptorSetLayout; MAXFRAMESINFLIGHT] = [descriptor_set_layout; MAXFRAMESINFLIGHT];
@@ -804,11 +1316,11 @@ fn create_descriptor_sets(
             range: ubo_size,
         };
 
-        let image_info = vk::DescriptorImageInfo {
-            sampler: textures_sampler,
+        let image_infos = material_samplers.map(|sampler| vk::DescriptorImageInfo {
+            sampler,
             image_view: texture_image_view,
-            image_layout: vk::ImageLayout::GENERAL,
-        };
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        });
 
         let descriptor_writes = [
             vk::WriteDescriptorSet {
@@ -825,8 +1337,8 @@ fn create_descriptor_sets(
                 dst_binding: 1,
                 dst_array_element: 0,
                 descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: 1,
-                p_image_info: &image_info,
+                descriptor_count: image_infos.len() as _,
+                p_image_info: image_infos.as_ptr(),
                 ..Default::default()
             }
         ];
@@ -834,9 +1346,33 @@ fn create_descriptor_sets(
         unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
     }
 
+    write_shadow_descriptors(device, &descriptor_sets, shadow_map);
+
     descriptor_sets
 }
 
+fn write_shadow_descriptors(device: &ash::Device, descriptor_sets: &[vk::DescriptorSet], shadow_map: &ShadowMap) {
+    let image_info = vk::DescriptorImageInfo {
+        sampler: shadow_map.sampler,
+        image_view: shadow_map.image.view,
+        image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+    };
+
+    for descriptor_set in descriptor_sets {
+        let descriptor_write = vk::WriteDescriptorSet {
+            dst_set: *descriptor_set,
+            dst_binding: 2,
+            dst_array_element: 0,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            p_image_info: &image_info,
+            ..Default::default()
+        };
+
+        unsafe { device.update_descriptor_sets(&[descriptor_write], &[]) };
+    }
+}
+
 fn create_ui_descriptor_sets(
     device: &ash::Device,
     descriptor_pool: vk::DescriptorPool,
@@ -5,11 +5,18 @@ use winit::event_loop::EventLoop;
 
 mod graphics;
 mod game;
+mod golden;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--golden") {
+        let passed = golden::run(args.iter().any(|arg| arg == "--bless"));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     let event_loop = EventLoop::new().unwrap();
     let mut application = App::run();
 
     event_loop.run_app(&mut application).unwrap();
     application.renderer.borrow_mut().destroy();
-}
\ No newline at end of file
+}